@@ -74,15 +74,29 @@ fn test<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     tests::queue::test_queue_split(&mut ring, &test)?;
     tests::queue::test_debug_print(&mut ring, &test)?;
     tests::queue::test_msg_ring_data(&mut ring, &test)?;
+    tests::queue::test_send_msg_ring(&mut ring, &test)?;
     tests::queue::test_msg_ring_send_fd(&mut ring, &test)?;
+    tests::queue::test_ring_channel(&mut ring, &test)?;
 
     tests::queue::test_batch(&mut ring, &test)?;
+    tests::queue::test_push_or_submit(&mut ring, &test)?;
+    tests::queue::test_sq_handle(&mut ring, &test)?;
+    tests::queue::test_setup_no_mmap::<S, C>(&test)?;
+    tests::queue::test_setup_no_sqarray::<S, C>(&test)?;
+    tests::queue::test_resize_rings::<S, C>(&test)?;
 
     // register
     tests::register::test_register_files_sparse(&mut ring, &test)?;
+    tests::register::test_register_file_alloc_range(&mut ring, &test)?;
+    tests::register::test_register_ring_fd(&mut ring, &test)?;
+    tests::register::test_register_napi(&mut ring, &test)?;
     tests::register_buffers::test_register_buffers(&mut ring, &test)?;
     tests::register_buffers::test_register_buffers_update(&mut ring, &test)?;
+    tests::register_buffers::test_clone_buffers(&mut ring, &test)?;
     tests::register_buf_ring::test_register_buf_ring(&mut ring, &test)?;
+    tests::register_buf_ring::test_buf_ring(&mut ring, &test)?;
+    tests::register_buf_ring::test_buf_ring_mmap(&mut ring, &test)?;
+    tests::register_buf_ring::test_buf_ring_incremental(&mut ring, &test)?;
     tests::register_sync_cancel::test_register_sync_cancel(&mut ring, &test)?;
     tests::register_sync_cancel::test_register_sync_cancel_unsubmitted(&mut ring, &test)?;
     tests::register_sync_cancel::test_register_sync_cancel_any(&mut ring, &test)?;
@@ -132,7 +146,11 @@ fn test<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     tests::timeout::test_timeout_abs(&mut ring, &test)?;
     tests::timeout::test_timeout_submit_args(&mut ring, &test)?;
     tests::timeout::test_timeout_submit_args_min_wait(&mut ring, &test)?;
+    tests::timeout::test_timeout_submit_args_abs(&mut ring, &test)?;
+    tests::timeout::test_timeout_registered_wait::<S, C>(&test)?;
+    tests::timeout::test_timeout_wait(&mut ring, &test)?;
     tests::timeout::test_timeout_multishot(&mut ring, &test)?;
+    tests::timeout::test_timeout_link_chain(&mut ring, &test)?;
 
     // net
     tests::net::test_tcp_write_read(&mut ring, &test)?;
@@ -142,6 +160,7 @@ fn test<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     tests::net::test_tcp_zero_copy_send_recv(&mut ring, &test)?;
     tests::net::test_tcp_zero_copy_send_fixed(&mut ring, &test)?;
     tests::net::test_tcp_sendmsg_recvmsg(&mut ring, &test)?;
+    tests::net::test_msghdr(&mut ring, &test)?;
     tests::net::test_tcp_zero_copy_sendmsg_recvmsg(&mut ring, &test)?;
     tests::net::test_tcp_accept(&mut ring, &test)?;
     tests::net::test_tcp_accept_file_index(&mut ring, &test)?;
@@ -152,6 +171,7 @@ fn test<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     tests::net::test_tcp_buffer_select_recvmsg(&mut ring, &test)?;
     tests::net::test_tcp_buffer_select_readv(&mut ring, &test)?;
     tests::net::test_tcp_recv_multi(&mut ring, &test)?;
+    tests::net::test_tcp_recv_multi_rearm(&mut ring, &test)?;
     tests::net::test_tcp_recv_bundle(&mut ring, &test)?;
     tests::net::test_tcp_recv_multi_bundle(&mut ring, &test)?;
 
@@ -160,6 +180,7 @@ fn test<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     tests::net::test_socket_bind_listen(&mut ring, &test)?;
     tests::net::test_udp_recvmsg_multishot(&mut ring, &test)?;
     tests::net::test_udp_recvmsg_multishot_trunc(&mut ring, &test)?;
+    tests::net::test_udp_recvmsg_multishot_control(&mut ring, &test)?;
     tests::net::test_udp_send_with_dest(&mut ring, &test)?;
     tests::net::test_udp_sendzc_with_dest(&mut ring, &test)?;
 
@@ -183,6 +204,17 @@ fn test<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     // os (process)
     tests::os::test_waitid(&mut ring, &test)?;
 
+    // pool
+    tests::pool::test_ring_pool::<S, C>(&test)?;
+
+    // runtime
+    tests::runtime::test_runtime_reactor::<S, C>(&test)?;
+    tests::runtime::test_runtime_foreign_cqe::<S, C>(&test)?;
+
+    // safe
+    tests::safe::test_safe_ring::<S, C>(&test)?;
+    tests::safe::test_safe_ring_foreign_cqe::<S, C>(&test)?;
+
     // regression test
     tests::regression::test_issue154(&mut ring, &test)?;
 
@@ -227,6 +259,8 @@ fn test_sqpoll<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     };
 
     tests::sqpoll::test_sqpoll_cq_overflow(&mut ring, &test)?;
+    tests::queue::test_push_or_submit(&mut ring, &test)?;
+    tests::queue::test_sq_handle(&mut ring, &test)?;
 
     println!("Test count: {}", test.count.get());
 
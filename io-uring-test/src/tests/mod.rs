@@ -6,12 +6,15 @@ pub mod net;
 pub mod os;
 pub mod pipe;
 pub mod poll;
+pub mod pool;
 pub mod queue;
 pub mod register;
 pub mod register_buf_ring;
 pub mod register_buffers;
 pub mod register_sync_cancel;
 pub mod regression;
+pub mod runtime;
+pub mod safe;
 pub mod timeout;
 
 pub mod sqpoll;
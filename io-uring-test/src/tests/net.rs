@@ -1222,6 +1222,89 @@ pub fn test_tcp_recv_multi<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     Ok(())
 }
 
+pub fn test_tcp_recv_multi_rearm<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    use io_uring::buf_ring::BufRing;
+    use io_uring::multishot::{Multishot, Status};
+
+    require!(
+        test;
+        test.probe.is_supported(opcode::Recv::CODE);
+        test.probe.is_supported(opcode::SendZc::CODE); // also available 6.0, like the multishot for recv
+    );
+
+    println!("test tcp_recv_multi_rearm");
+
+    let (mut send_stream, recv_stream) = tcp_pair()?;
+
+    // A single buffer, so that a second receive runs out of buffers.
+    let buf_ring = BufRing::builder(0xdeaf)
+        .ring_entries(1)
+        .buf_len(64)
+        .build(&ring.submitter())?;
+
+    let recv_e = opcode::RecvMulti::new(types::Fd(recv_stream.as_raw_fd()), 0xdeaf)
+        .build()
+        .user_data(0x23);
+    let mut recv = Multishot::new(recv_e);
+
+    let next = |ring: &mut IoUring<S, C>, recv: &mut Multishot| -> anyhow::Result<_> {
+        if !recv.is_armed() {
+            unsafe {
+                let queue = ring.submission();
+                queue.sync();
+                recv.arm(&queue).expect("queue is full");
+                queue.sync();
+            }
+        }
+        ring.submit_and_wait(1)?;
+
+        let cqe: cqueue::Entry = ring.completion().next().expect("cqueue is empty").into();
+        let status = unsafe {
+            let queue = ring.submission();
+            queue.sync();
+            let status = recv.update_and_rearm(&cqe, &queue);
+            queue.sync();
+            status.expect("unexpected completion")
+        };
+        Ok((status, cqe.result(), cqe.flags()))
+    };
+
+    send_stream.write_all(b"hello")?;
+    let (status, result, flags) = next(ring, &mut recv)?;
+    assert_eq!(status, Status::More);
+    assert!(recv.is_armed());
+    let buf = buf_ring.get(flags, result as usize).unwrap();
+    assert_eq!(&*buf, b"hello");
+
+    // The buffer is still held, so the kernel ends the request.
+    send_stream.write_all(b"world")?;
+    let (status, result, _) = next(ring, &mut recv)?;
+    assert_eq!(status, Status::Rearm);
+    assert_eq!(result, -libc::ENOBUFS);
+    assert!(recv.is_armed());
+    assert_eq!(recv.rearms(), 1);
+    drop(buf);
+
+    // The request was pushed again, and receives the pending data once submitted.
+    let (status, result, flags) = next(ring, &mut recv)?;
+    assert_eq!(status, Status::More);
+    assert_eq!(recv.rearms(), 1);
+    let buf = buf_ring.get(flags, result as usize).unwrap();
+    assert_eq!(&*buf, b"world");
+    drop(buf);
+
+    send_stream.shutdown(Shutdown::Write)?;
+    let (status, result, _) = next(ring, &mut recv)?;
+    assert_eq!(status, Status::Done);
+    assert_eq!(result, 0);
+    assert!(recv.is_done());
+
+    Ok(())
+}
+
 pub fn test_tcp_recv_bundle<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     ring: &mut IoUring<S, C>,
     test: &Test,
@@ -2485,3 +2568,292 @@ pub fn test_tcp_recvzc<S: squeue::EntryMarker>(test: &Test) -> anyhow::Result<()
 
     Ok(())
 }
+
+pub fn test_msghdr<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    use std::os::unix::net::UnixStream;
+
+    require!(
+        test;
+        test.probe.is_supported(opcode::SendMsg::CODE);
+        test.probe.is_supported(opcode::RecvMsg::CODE);
+    );
+
+    println!("test msghdr");
+
+    // UDP, with a destination address, segmentation and packet info
+    let send_socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    let recv_socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    let on: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            recv_socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_PKTINFO,
+            &on as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as _,
+        )
+    };
+    assert_eq!(ret, 0);
+
+    let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+    let send_msg = types::MsgHdr::new()
+        .buf(&payload[..1000])
+        .buf(&payload[1000..])
+        .addr(recv_socket.local_addr()?)
+        .udp_segment(1000)
+        .pktinfo(0, "127.0.0.1".parse()?);
+
+    let mut buf = vec![0; 2000];
+    let mut recv_msg = types::MsgHdr::new()
+        .buf_mut(&mut buf)
+        .recv_addr()
+        .control_capacity(types::cmsg_space(mem::size_of::<libc::in_pktinfo>()));
+
+    let send_e = opcode::SendMsg::new(Fd(send_socket.as_raw_fd()), send_msg.as_ptr());
+    let recv_e = opcode::RecvMsg::new(Fd(recv_socket.as_raw_fd()), recv_msg.as_mut_ptr().unwrap());
+
+    let queue = ring.submission();
+    queue.sync();
+    unsafe {
+        queue
+            .push(&send_e.build().user_data(0x01).into())
+            .expect("queue is full");
+        queue
+            .push(&recv_e.build().user_data(0x02).into())
+            .expect("queue is full");
+    }
+    queue.sync();
+
+    ring.submit_and_wait(2)?;
+
+    let mut cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    cqes.sort_by_key(|cqe| cqe.user_data());
+
+    assert_eq!(cqes.len(), 2);
+    assert_eq!(cqes[0].user_data(), 0x01);
+    assert_eq!(cqes[0].result(), 3000);
+    assert_eq!(cqes[1].user_data(), 0x02);
+    assert_eq!(cqes[1].result(), 1000);
+    assert_eq!(recv_msg.source_addr(), Some(send_socket.local_addr()?));
+
+    let control = recv_msg.control();
+    assert!(control.len() >= mem::size_of::<libc::cmsghdr>());
+    let cmsg: libc::cmsghdr = unsafe { std::ptr::read_unaligned(control.as_ptr().cast()) };
+    assert_eq!(cmsg.cmsg_level, libc::IPPROTO_IP);
+    assert_eq!(cmsg.cmsg_type, libc::IP_PKTINFO);
+
+    drop(recv_msg);
+    assert_eq!(&buf[..1000], &payload[..1000]);
+
+    // unix, with file descriptors
+    let (send_stream, recv_stream) = UnixStream::pair()?;
+    let (passed, mut peer) = UnixStream::pair()?;
+
+    let text = b"fd";
+    // Room reserved for receiving is not passed along with the control messages to send.
+    let send_msg = types::MsgHdr::new()
+        .buf(text)
+        .rights(&[passed.as_raw_fd()])
+        .control_capacity(256);
+    // `msg_controllen` is not a `usize` on every libc.
+    let controllen: usize = unsafe { (*send_msg.as_ptr()).msg_controllen as _ };
+    assert_eq!(controllen, types::cmsg_space(mem::size_of::<libc::c_int>()));
+
+    // Read-only buffers cannot be received into.
+    let mut read_only = types::MsgHdr::new().buf(text);
+    assert!(read_only.as_mut_ptr().is_none());
+
+    let mut buf = vec![0; text.len()];
+    let mut recv_msg = types::MsgHdr::new()
+        .buf_mut(&mut buf)
+        .control_capacity(types::cmsg_space(mem::size_of::<libc::c_int>()));
+
+    let send_e = opcode::SendMsg::new(Fd(send_stream.as_raw_fd()), send_msg.as_ptr());
+    let recv_e = opcode::RecvMsg::new(Fd(recv_stream.as_raw_fd()), recv_msg.as_mut_ptr().unwrap());
+
+    let queue = ring.submission();
+    queue.sync();
+    unsafe {
+        queue
+            .push(&send_e.build().user_data(0x03).into())
+            .expect("queue is full");
+        queue
+            .push(&recv_e.build().user_data(0x04).into())
+            .expect("queue is full");
+    }
+    queue.sync();
+
+    ring.submit_and_wait(2)?;
+
+    let mut cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    cqes.sort_by_key(|cqe| cqe.user_data());
+
+    assert_eq!(cqes.len(), 2);
+    assert_eq!(cqes[0].result(), text.len() as i32);
+    assert_eq!(cqes[1].result(), text.len() as i32);
+
+    let messages: Vec<_> = recv_msg.control_messages().collect();
+    let fd = match &messages[..] {
+        [types::ControlMessage::Rights(fds)] if fds.len() == 1 => fds[0],
+        _ => panic!("{:?}", messages),
+    };
+
+    drop(recv_msg);
+    assert_eq!(buf, text);
+
+    // The received descriptor refers to the same socket.
+    let mut received = unsafe { UnixStream::from_raw_fd(fd) };
+    drop(passed);
+    received.write_all(b"ok")?;
+    drop(received);
+    let mut out = Vec::new();
+    peer.read_to_end(&mut out)?;
+    assert_eq!(out, b"ok");
+
+    Ok(())
+}
+
+pub fn test_udp_recvmsg_multishot_control<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    use types::ControlMessage;
+
+    // Multishot recvmsg was introduced in 6.0, like `SendZc`.
+    require!(
+        test;
+        test.probe.is_supported(opcode::RecvMsgMulti::CODE);
+        test.probe.is_supported(opcode::ProvideBuffers::CODE);
+        test.probe.is_supported(opcode::SendMsgZc::CODE);
+        test.probe.is_supported(opcode::SendMsg::CODE);
+    );
+
+    println!("test udp_recvmsg_multishot_control");
+
+    // SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE
+    const TIMESTAMPING: libc::c_int = 0x8 | 0x10;
+
+    let server_socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    let client_socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    for (level, name, value) in [
+        (libc::SOL_UDP, libc::UDP_GRO, 1),
+        (libc::IPPROTO_IP, libc::IP_PKTINFO, 1),
+        (libc::SOL_SOCKET, libc::SO_TIMESTAMPING, TIMESTAMPING),
+    ] {
+        let ret = unsafe {
+            libc::setsockopt(
+                server_socket.as_raw_fd(),
+                level,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as _,
+            )
+        };
+        assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+    }
+
+    // The kernel turns receive timestamps on from a work queue, so wait for a warm-up datagram
+    // to get one before the datagrams of the test are sent.
+    let mut stamped = false;
+    for _ in 0..100 {
+        client_socket.send_to(b"warm-up", server_socket.local_addr()?)?;
+        let mut buf = [0u8; 16];
+        let mut msg = types::MsgHdr::new().buf_mut(&mut buf).control_capacity(256);
+        let ret = unsafe { libc::recvmsg(server_socket.as_raw_fd(), msg.as_mut_ptr().unwrap(), 0) };
+        assert!(ret >= 0, "{}", io::Error::last_os_error());
+        stamped = msg
+            .control_messages()
+            .any(|message| matches!(message, ControlMessage::Timestamping(_)));
+        if stamped {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(stamped);
+
+    const BUF_GROUP: u16 = 34;
+    let mut buffer = vec![0u8; 4096];
+    let provide_bufs_e =
+        opcode::ProvideBuffers::new(buffer.as_mut_ptr(), buffer.len() as i32, 1, BUF_GROUP, 0);
+
+    let queue = ring.submission();
+    queue.sync();
+    unsafe {
+        queue
+            .push(&provide_bufs_e.build().user_data(0x01).into())
+            .expect("queue is full");
+    }
+    queue.sync();
+
+    ring.submit_and_wait(1)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].result(), 0);
+
+    // Only the name and control lengths of the template are used.
+    let template = types::MsgHdr::new().recv_addr().control_capacity(256);
+    let recvmsg_e =
+        opcode::RecvMsgMulti::new(Fd(server_socket.as_raw_fd()), template.as_ptr(), BUF_GROUP);
+
+    // A single send of three 1000 bytes datagrams, coalesced again by the receiver.
+    let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+    let send_msg = types::MsgHdr::new()
+        .buf(&payload)
+        .addr(server_socket.local_addr()?)
+        .udp_segment(1000);
+    let sendmsg_e = opcode::SendMsg::new(Fd(client_socket.as_raw_fd()), send_msg.as_ptr());
+
+    let queue = ring.submission();
+    queue.sync();
+    unsafe {
+        queue
+            .push(&recvmsg_e.build().user_data(0x02).into())
+            .expect("queue is full");
+        queue
+            .push(&sendmsg_e.build().user_data(0x03).into())
+            .expect("queue is full");
+    }
+    queue.sync();
+
+    // The send, the message, and the end of the request as the buffers ran out.
+    ring.submit_and_wait(3)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    assert_eq!(cqes.len(), 3);
+
+    let mut received = false;
+    for cqe in cqes {
+        match cqe.user_data() {
+            0x02 if cqueue::more(cqe.flags()) => {
+                assert!(cqe.result() > 0, "{:?}", cqe.result());
+                assert_eq!(cqueue::buffer_select(cqe.flags()), Some(0));
+                let msg = types::RecvMsgOut::parse(&buffer, template.as_msghdr()).unwrap();
+                assert_eq!(msg.payload_data(), &payload[..]);
+                assert!(!msg.is_control_data_truncated());
+
+                let messages: Vec<_> = msg.control_messages().collect();
+                assert_eq!(messages.len(), 3, "{:?}", messages);
+                assert!(messages.contains(&ControlMessage::UdpGroSegment(1000)));
+                assert!(messages.iter().any(|message| matches!(
+                    message,
+                    ControlMessage::PktInfo { dst, .. } if dst.is_loopback()
+                )));
+                assert!(messages.iter().any(|message| matches!(
+                    message,
+                    ControlMessage::Timestamping([software, ..]) if !software.is_zero()
+                )));
+                received = true;
+            }
+            0x02 => assert_eq!(cqe.result(), -libc::ENOBUFS),
+            0x03 => assert_eq!(cqe.result(), payload.len() as i32),
+            _ => unreachable!(),
+        }
+    }
+    assert!(received);
+
+    Ok(())
+}
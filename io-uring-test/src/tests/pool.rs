@@ -0,0 +1,179 @@
+use crate::Test;
+use io_uring::pool::{pin_thread, RingPool};
+use io_uring::register::Restriction;
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+
+pub fn test_ring_pool<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    // IORING_REGISTER_IOWQ_MAX_WORKERS needs 5.15, checked with an opcode of the same release.
+    require!(
+        test;
+        test.probe.is_supported(opcode::MkDirAt::CODE);
+    );
+
+    println!("test ring_pool");
+
+    fn nop<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+        ring: &mut IoUring<S, C>,
+        entry: squeue::Entry,
+    ) -> anyhow::Result<i32> {
+        unsafe {
+            let queue = ring.submission();
+            queue.sync();
+            queue
+                .push(&entry.user_data(0x42).into())
+                .expect("queue is full");
+            queue.sync();
+        }
+        ring.submit_and_wait(1)?;
+
+        let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+        assert_eq!(cqes.len(), 1);
+        assert_eq!(cqes[0].user_data(), 0x42);
+        Ok(cqes[0].result())
+    }
+
+    // rings with the same limits, files and restrictions
+
+    let pool = RingPool::<S, C>::builder()
+        .cores(&[0])
+        .iowq_max_workers([2, 2])
+        .files_sparse(4)
+        .restrictions(&[Restriction::sqe_op(opcode::Nop::CODE)])
+        .build(3, 8)?;
+
+    assert_eq!(pool.len(), 3);
+    assert_eq!(pool.core(2), Some(0));
+    assert_eq!(pool.core(3), None);
+    assert!(pool.probe().is_supported(opcode::Nop::CODE));
+
+    let cores: Vec<_> = (0..pool.len()).map(|i| pool.core(i)).collect();
+    std::thread::scope(|s| {
+        let mut threads = Vec::new();
+        for (mut ring, core) in pool.into_rings().into_iter().zip(cores) {
+            threads.push(s.spawn(move || -> anyhow::Result<()> {
+                assert!(!ring.params().is_setup_sqpoll());
+                pin_thread(&ring, core.unwrap())?;
+
+                // The restrictions don't allow any register opcode.
+                let mut max = [0; 2];
+                let err = ring.submitter().register_iowq_max_workers(&mut max);
+                assert_eq!(err.unwrap_err().raw_os_error(), Some(libc::EACCES));
+
+                let mut buf = [0u8; 8];
+                assert_eq!(nop(&mut ring, opcode::Nop::new().build())?, 0);
+                let read_e = opcode::Read::new(types::Fixed(0), buf.as_mut_ptr(), buf.len() as _);
+                assert_eq!(nop(&mut ring, read_e.build())?, -libc::EACCES);
+                Ok(())
+            }));
+        }
+        threads
+            .into_iter()
+            .try_for_each(|thread| thread.join().unwrap())
+    })?;
+
+    // the threads driving the rings and their async workers are pinned to their core
+
+    let pool = RingPool::<S, C>::builder().cores(&[0]).build(2, 8)?;
+    std::thread::scope(|s| {
+        let pool = &pool;
+        let mut threads = Vec::new();
+        for index in 0..pool.len() {
+            threads.push(s.spawn(move || -> anyhow::Result<()> {
+                pool.init_thread(index)?;
+
+                let tid = unsafe { libc::syscall(libc::SYS_gettid) };
+                assert_eq!(cpus_allowed(&tid.to_string())?, "0");
+
+                // An async request starts a worker for this thread, which lingers after it.
+                // The workers belong to the thread, whichever ring it submits to.
+                let mut ring = IoUring::<S, C>::builder().build(4)?;
+                let nop_e = opcode::Nop::new().build().flags(squeue::Flags::ASYNC);
+                assert_eq!(nop(&mut ring, nop_e)?, 0);
+
+                let workers = threads_named(&format!("iou-wrk-{}", tid))?;
+                assert!(!workers.is_empty());
+                for worker in workers {
+                    assert_eq!(cpus_allowed(&worker)?, "0");
+                }
+                Ok(())
+            }));
+        }
+        threads
+            .into_iter()
+            .try_for_each(|thread| thread.join().unwrap())
+    })?;
+
+    // rings with their own SQPOLL thread, pinned to their core
+
+    let pool = RingPool::<S, C>::builder()
+        .cores(&[0])
+        .sqpoll(1000)
+        .build(1, 8)?;
+    assert!(pool.get(0).unwrap().params().is_setup_sqpoll());
+    // The SQPOLL thread names itself once it runs.
+    let mut sqpoll_threads = Vec::new();
+    for _ in 0..100 {
+        sqpoll_threads = threads_named("iou-sqp-")?;
+        if !sqpoll_threads.is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!sqpoll_threads.is_empty());
+    for task in sqpoll_threads {
+        assert_eq!(cpus_allowed(&task)?, "0");
+    }
+    drop(pool);
+
+    // a shared SQPOLL thread can't be pinned to several cores
+
+    let err = RingPool::<S, C>::builder()
+        .cores(&[0])
+        .shared_sqpoll(1000)
+        .build(2, 8)
+        .err()
+        .unwrap();
+    assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+    // rings sharing an SQPOLL thread
+
+    let pool = RingPool::<S, C>::builder()
+        .shared_sqpoll(1000)
+        .iowq_max_workers([2, 3])
+        .build(2, 8)?;
+    for mut ring in pool.into_rings() {
+        assert!(ring.params().is_setup_sqpoll());
+
+        let mut max = [0; 2];
+        ring.submitter().register_iowq_max_workers(&mut max)?;
+        assert_eq!(max, [2, 3]);
+        assert_eq!(nop(&mut ring, opcode::Nop::new().build())?, 0);
+    }
+
+    Ok(())
+}
+
+// The tasks of this process whose name starts with `prefix`.
+fn threads_named(prefix: &str) -> std::io::Result<Vec<String>> {
+    let mut tasks = Vec::new();
+    for entry in std::fs::read_dir("/proc/self/task")? {
+        let task = entry?.file_name().into_string().unwrap();
+        // The task may have exited in the meantime.
+        let comm = std::fs::read_to_string(format!("/proc/self/task/{}/comm", task));
+        if matches!(comm, Ok(comm) if comm.starts_with(prefix)) {
+            tasks.push(task);
+        }
+    }
+    Ok(tasks)
+}
+
+fn cpus_allowed(task: &str) -> std::io::Result<String> {
+    let status = std::fs::read_to_string(format!("/proc/self/task/{}/status", task))?;
+    let line = status
+        .lines()
+        .find(|line| line.starts_with("Cpus_allowed_list:"))
+        .unwrap();
+    Ok(line["Cpus_allowed_list:".len()..].trim().to_owned())
+}
@@ -77,6 +77,101 @@ pub fn test_batch<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     Ok(())
 }
 
+pub fn test_push_or_submit<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    require! {
+        test;
+    }
+
+    println!("test push_or_submit");
+
+    assert!(ring.completion().is_empty());
+
+    // More entries than the submission queue can hold.
+    let count = ring.params().sq_entries() as usize + 4;
+    for i in 0..count {
+        let nop_e = opcode::Nop::new().build().user_data(0x50 + i as u64);
+        unsafe { ring.push_or_submit(&nop_e.into())? };
+    }
+
+    let mut cqes = Vec::new();
+    while cqes.len() < count {
+        ring.submit_and_wait(1)?;
+        cqes.extend(ring.completion().map(Into::<cqueue::Entry>::into));
+    }
+    cqes.sort_by_key(|cqe| cqe.user_data());
+
+    assert_eq!(cqes.len(), count);
+    for (i, cqe) in cqes.iter().enumerate() {
+        assert_eq!(cqe.user_data(), 0x50 + i as u64);
+        assert_eq!(cqe.result(), 0);
+    }
+
+    Ok(())
+}
+
+pub fn test_sq_handle<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    require! {
+        test;
+    }
+
+    println!("test sq_handle");
+
+    assert!(ring.completion().is_empty());
+
+    let threads = 4;
+    let per_thread = 6;
+    let handle = squeue::SqHandle::new(8);
+
+    let mut cqes: Vec<cqueue::Entry> = Vec::new();
+    std::thread::scope(|s| -> anyhow::Result<()> {
+        for t in 0..threads {
+            let handle = handle.clone();
+            s.spawn(move || {
+                for i in 0..per_thread {
+                    let nop_e = opcode::Nop::new().build().user_data((t << 8 | i) as u64);
+                    while unsafe { handle.push(nop_e.clone()).is_err() } {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+        }
+
+        // The owner moves the staged entries into the ring while the workers push.
+        while cqes.len() < threads * per_thread {
+            let sq = ring.submission();
+            sq.sync();
+            sq.push_staged(&handle);
+            sq.sync();
+            ring.submit()?;
+            cqes.extend(ring.completion().map(Into::<cqueue::Entry>::into));
+        }
+
+        Ok(())
+    })?;
+
+    assert!(handle.is_empty());
+    assert_eq!(cqes.len(), threads * per_thread);
+
+    // The entries of each thread are submitted in order.
+    for t in 0..threads {
+        let user_data: Vec<u64> = cqes
+            .iter()
+            .map(|cqe| cqe.user_data())
+            .filter(|user_data| user_data >> 8 == t as u64)
+            .collect();
+        let expected: Vec<u64> = (0..per_thread).map(|i| (t << 8 | i) as u64).collect();
+        assert_eq!(user_data, expected);
+    }
+
+    Ok(())
+}
+
 pub fn test_queue_split<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     ring: &mut IoUring<S, C>,
     test: &Test,
@@ -198,6 +293,48 @@ pub fn test_msg_ring_data<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     Ok(())
 }
 
+pub fn test_send_msg_ring<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    require!(
+        test;
+        test.probe.is_supported(opcode::MsgRingData::CODE);
+    );
+
+    println!("test send_msg_ring");
+
+    // Post to the test ring from a thread which does not own any ring.
+    let fd = ring.as_raw_fd();
+    let res = std::thread::spawn(move || io_uring::send_msg_ring(fd, 0x44, 7, Some(0x10000)))
+        .join()
+        .unwrap();
+
+    // Posting without a ring was introduced in kernel 6.13, older kernels
+    // reject the -1 ring fd.
+    match res {
+        Ok(()) => {}
+        Err(e) if matches!(e.raw_os_error(), Some(libc::EBADF | libc::EINVAL)) => {
+            println!("send_msg_ring not supported, skipping");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    ring.submit_and_wait(1)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].user_data(), 0x44);
+    assert_eq!(cqes[0].result(), 7);
+    assert_eq!(cqes[0].flags(), 0x10000);
+
+    Ok(())
+}
+
 pub fn test_msg_ring_send_fd<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     ring: &mut IoUring<S, C>,
     test: &Test,
@@ -304,3 +441,320 @@ pub fn test_msg_ring_send_fd<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
 
     Ok(())
 }
+
+pub fn test_ring_channel<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    use io_uring::channel::{Message, RingChannel};
+    use std::os::unix::io::AsRawFd;
+
+    // See `test_msg_ring_send_fd` for the `IORING_OP_SEND_ZC` proxy.
+    require!(
+        test;
+        test.probe.is_supported(opcode::MsgRingData::CODE);
+        test.probe.is_supported(opcode::SendZc::CODE);
+    );
+
+    println!("test ring_channel");
+
+    let mut worker = IoUring::new(8)?;
+    worker.submitter().register_files_sparse(2)?;
+    let channel = RingChannel::new(0x77);
+
+    // A message, only the target ring gets a completion.
+    {
+        let queue = ring.submission();
+        queue.sync();
+        channel
+            .send(&queue, worker.as_raw_fd(), String::from("hello"))
+            .expect("queue is full");
+        queue.sync();
+    }
+    ring.submit_and_wait(0)?;
+    worker.submit_and_wait(1)?;
+
+    assert!(ring.completion().is_empty());
+    let cqes: Vec<cqueue::Entry> = worker.completion().collect();
+    assert_eq!(cqes.len(), 1);
+    assert!(channel.owns(cqes[0].user_data()));
+    match channel.recv(&cqes[0]) {
+        Some(Message::Received { msg, result }) => {
+            assert_eq!(msg, "hello");
+            assert_eq!(result, 0);
+        }
+        msg => panic!("unexpected {:?}", msg),
+    }
+
+    // A message along with a fixed file, installed in a slot allocated by the kernel.
+    let _ = ring.submitter().unregister_files();
+    let tmp = tempfile::tempfile()?;
+    ring.submitter().register_files(&[tmp.as_raw_fd()])?;
+    {
+        let queue = ring.submission();
+        queue.sync();
+        channel
+            .send_fd(
+                &queue,
+                worker.as_raw_fd(),
+                types::Fixed(0),
+                types::DestinationSlot::auto_target(),
+                String::from("conn"),
+            )
+            .expect("queue is full");
+        queue.sync();
+    }
+    ring.submit_and_wait(0)?;
+    worker.submit_and_wait(1)?;
+
+    assert!(ring.completion().is_empty());
+    let cqes: Vec<cqueue::Entry> = worker.completion().collect();
+    assert_eq!(cqes.len(), 1);
+    match channel.recv(&cqes[0]) {
+        Some(Message::Received { msg, result }) => {
+            assert_eq!(msg, "conn");
+            assert!((0..2).contains(&result));
+        }
+        msg => panic!("unexpected {:?}", msg),
+    }
+    ring.submitter().unregister_files()?;
+
+    // A failed send hands the message back on the sending ring.
+    {
+        let queue = ring.submission();
+        queue.sync();
+        channel
+            .send(&queue, tmp.as_raw_fd(), String::from("lost"))
+            .expect("queue is full");
+        queue.sync();
+    }
+    ring.submit_and_wait(1)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    assert_eq!(cqes.len(), 1);
+    match channel.recv(&cqes[0]) {
+        Some(Message::Failed { msg, error }) => {
+            assert_eq!(msg, "lost");
+            assert_eq!(error.raw_os_error(), Some(libc::EBADFD));
+        }
+        msg => panic!("unexpected {:?}", msg),
+    }
+
+    // A fixed file sent without a completion on the target ring.
+    ring.submitter().register_files(&[tmp.as_raw_fd()])?;
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue
+            .push(
+                &opcode::MsgRingSendFd::new(
+                    types::Fd(worker.as_raw_fd()),
+                    types::Fixed(0),
+                    types::DestinationSlot::auto_target(),
+                    0x99,
+                )
+                .opcode_flags(types::MsgRingFlags::CQE_SKIP)
+                .build()
+                .user_data(0x98)
+                .into(),
+            )
+            .expect("queue is full");
+        queue.sync();
+    }
+    ring.submit_and_wait(1)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].user_data(), 0x98);
+    assert!(cqes[0].result() >= 0);
+    ring.submitter().unregister_files()?;
+
+    assert!(channel.is_empty());
+    assert!(worker.completion().is_empty());
+
+    Ok(())
+}
+
+pub fn test_setup_no_mmap<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    require! {
+        test;
+    }
+
+    println!("test setup_no_mmap");
+
+    // IORING_SETUP_NO_MMAP was introduced in kernel 6.5, older kernels reject the flag.
+    let mut ring = match IoUring::<S, C>::builder().setup_no_mmap().build(8) {
+        Ok(ring) => ring,
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+            println!("setup_no_mmap not supported, skipping");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    assert!(ring.params().is_setup_no_mmap());
+
+    let nop_e = opcode::Nop::new().build().user_data(0x42).into();
+
+    unsafe {
+        let queue = ring.submission();
+        queue.push(&nop_e).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(1)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].user_data(), 0x42);
+    assert_eq!(cqes[0].result(), 0);
+
+    Ok(())
+}
+
+pub fn test_setup_no_sqarray<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    require! {
+        test;
+    }
+
+    println!("test setup_no_sqarray");
+
+    // Kernels before 6.6 reject the flag, in which case the builder falls back
+    // to the indirection array. The ring must work the same either way.
+    let mut ring = IoUring::<S, C>::builder().setup_no_sqarray().build(4)?;
+
+    if !ring.params().is_setup_no_sqarray() {
+        println!("setup_no_sqarray not supported, using indirection array");
+    }
+
+    // Go around the ring a few times to exercise the head wrapping.
+    for round in 0..3u64 {
+        unsafe {
+            let queue = ring.submission();
+            // Pick up the entries consumed by the kernel in the previous round.
+            queue.sync();
+            for i in 0..4 {
+                let nop_e = opcode::Nop::new().build().user_data(round * 4 + i).into();
+                queue.push(&nop_e).expect("queue is full");
+            }
+            queue.sync();
+        }
+
+        ring.submit_and_wait(4)?;
+
+        let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+
+        assert_eq!(cqes.len(), 4);
+        for (i, cqe) in cqes.iter().enumerate() {
+            assert_eq!(cqe.user_data(), round * 4 + i as u64);
+            assert_eq!(cqe.result(), 0);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn test_resize_rings<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    require! {
+        test;
+    }
+
+    println!("test resize_rings");
+
+    // Resizing requires IORING_SETUP_DEFER_TASKRUN, introduced in kernel 6.1.
+    let mut ring = match IoUring::<S, C>::builder()
+        .setup_single_issuer()
+        .setup_defer_taskrun()
+        .setup_no_sqarray()
+        .build(4)
+    {
+        Ok(ring) => ring,
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+            println!("setup_defer_taskrun not supported, skipping");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // Without IORING_SETUP_NO_SQARRAY, introduced in kernel 6.6, the new indirection array
+    // cannot be found.
+    if !ring.params().is_setup_no_sqarray() {
+        let err = ring.resize(32, 64).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        println!("setup_no_sqarray not supported, skipping");
+        return Ok(());
+    }
+
+    // Leave a completion in the CQ, a synchronized entry and an unsynchronized
+    // entry in the SQ. All of them must survive the resize.
+    unsafe {
+        let queue = ring.submission();
+        let nop_e = opcode::Nop::new().build().user_data(0).into();
+        queue.push(&nop_e).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(1)?;
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        let nop_e = opcode::Nop::new().build().user_data(1).into();
+        queue.push(&nop_e).expect("queue is full");
+        queue.sync();
+        let nop_e = opcode::Nop::new().build().user_data(2).into();
+        queue.push(&nop_e).expect("queue is full");
+    }
+
+    // IORING_REGISTER_RESIZE_RINGS was introduced in kernel 6.13.
+    match ring.resize(32, 64) {
+        Ok(()) => {}
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+            println!("resize_rings not supported, skipping");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    assert_eq!(ring.params().sq_entries(), 32);
+    assert_eq!(ring.params().cq_entries(), 64);
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        assert_eq!(queue.capacity(), 32);
+        for i in 3..32 {
+            let nop_e = opcode::Nop::new().build().user_data(i).into();
+            queue.push(&nop_e).expect("queue is full");
+        }
+        queue.sync();
+    }
+
+    ring.submit_and_wait(31)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+
+    assert_eq!(cqes.len(), 32);
+    for (i, cqe) in cqes.iter().enumerate() {
+        assert_eq!(cqe.user_data(), i as u64);
+        assert_eq!(cqe.result(), 0);
+    }
+
+    // Rings with an indirection array are left untouched.
+    let mut ring = IoUring::<S, C>::builder()
+        .setup_single_issuer()
+        .setup_defer_taskrun()
+        .build(4)?;
+    let err = ring.resize(32, 64).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    assert_eq!(ring.params().sq_entries(), 4);
+
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use crate::Test;
-use io_uring::{cqueue, opcode, squeue, IoUring};
+use io_uring::register::{Napi, NapiTracking};
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+use std::os::unix::io::AsRawFd;
 
 pub fn test_register_files_sparse<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     ring: &mut IoUring<S, C>,
@@ -68,3 +70,291 @@ pub fn test_register_files_sparse<S: squeue::EntryMarker, C: cqueue::EntryMarker
 
     Ok(())
 }
+
+pub fn test_register_file_alloc_range<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    // IORING_REGISTER_FILE_ALLOC_RANGE was introduced in kernel 6.0, as was the opcode for SendZc.
+    require!(
+        test;
+        test.probe.is_supported(opcode::SendZc::CODE);
+    );
+
+    println!("test register_file_alloc_range");
+
+    fn socket<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+        ring: &mut IoUring<S, C>,
+        dest: types::DestinationSlot,
+    ) -> anyhow::Result<i32> {
+        let op = opcode::Socket::new(libc::AF_INET, libc::SOCK_DGRAM, 0).file_index(Some(dest));
+        submit_one(ring, op.build().user_data(0x21).into())
+    }
+
+    fn submit_one<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+        ring: &mut IoUring<S, C>,
+        entry: S,
+    ) -> anyhow::Result<i32> {
+        Ok(submit_one_cqe(ring, entry)?.result())
+    }
+
+    fn submit_one_cqe<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+        ring: &mut IoUring<S, C>,
+        entry: S,
+    ) -> anyhow::Result<cqueue::Entry> {
+        unsafe {
+            let queue = ring.submission();
+            queue.sync();
+            queue.push(&entry).expect("queue is full");
+            queue.sync();
+        }
+        ring.submit_and_wait(1)?;
+
+        let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+        assert_eq!(cqes.len(), 1);
+        Ok(cqes[0].clone())
+    }
+
+    // Cleanup all fixed files (if any), then leave slots 2 and 3 to the kernel.
+    let _ = ring.submitter().unregister_files();
+    ring.submitter().register_files_sparse(4)?;
+    ring.submitter().register_file_alloc_range(2, 2)?;
+
+    let mut table = types::FixedFileTable::new(4, 2..4);
+
+    // The kernel only allocates from its range.
+    for _ in 0..2 {
+        let ret = socket(ring, types::DestinationSlot::auto_target())?;
+        assert!(ret >= 0, "auto allocation failed: {}", ret);
+        table
+            .insert_allocated(ret as u32)
+            .expect("slot outside of the allocation range");
+    }
+    assert_eq!(
+        socket(ring, types::DestinationSlot::auto_target())?,
+        -libc::ENFILE
+    );
+
+    // The rest of the table is ours.
+    let fixed = table.alloc().unwrap();
+    assert_eq!(fixed.0, 0);
+    let dest = types::DestinationSlot::try_from_slot_target(fixed.0).unwrap();
+    assert_eq!(socket(ring, dest)?, 0);
+
+    // Closing a kernel-allocated slot makes it available again.
+    assert_eq!(
+        submit_one(ring, opcode::Close::new(types::Fixed(3)).build().into())?,
+        0
+    );
+    assert!(table.release(types::Fixed(3)));
+    assert_eq!(socket(ring, types::DestinationSlot::auto_target())?, 3);
+    table.insert_allocated(3).unwrap();
+
+    // A close built by the table recycles the slot once it succeeded.
+    let close_e = table.close(fixed, 0x22).unwrap();
+    assert!(table.close(fixed, 0x23).is_none());
+    assert!(table.is_used(fixed));
+    let cqe = submit_one_cqe(ring, close_e.into())?;
+    assert_eq!(cqe.result(), 0);
+    assert_eq!(table.complete_close(&cqe).map(|slot| slot.0), Some(fixed.0));
+    assert!(!table.is_used(fixed));
+    assert_eq!(table.alloc().map(|slot| slot.0), Some(fixed.0));
+
+    // A failed close leaves the slot in use.
+    let close_e = table.close(fixed, 0x24).unwrap();
+    let cqe = submit_one_cqe(ring, close_e.into())?;
+    assert_eq!(cqe.result(), -libc::EBADF);
+    assert!(table.complete_close(&cqe).is_none());
+    assert!(table.is_used(fixed));
+
+    ring.submitter().unregister_files()?;
+
+    Ok(())
+}
+
+pub fn test_register_ring_fd<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    // IORING_REGISTER_RING_FDS was introduced in kernel 5.18, as was the opcode for MsgRingData.
+    require!(
+        test;
+        test.probe.is_supported(opcode::MsgRingData::CODE);
+    );
+
+    println!("test register_ring_fd");
+
+    let index = ring.submitter().register_ring_fd()?;
+    assert_eq!(ring.submitter().registered_ring_fd(), Some(index));
+
+    // A second registration of the same ring is rejected.
+    let err = ring.submitter().register_ring_fd().unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+
+    // Entering the kernel now goes through the registered index.
+    let nop_e = opcode::Nop::new().build().user_data(0x43).into();
+
+    unsafe {
+        let queue = ring.submission();
+        queue.push(&nop_e).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(1)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].user_data(), 0x43);
+    assert_eq!(cqes[0].result(), 0);
+
+    // The index is only valid for this thread, others enter with the file descriptor, even
+    // when they registered another ring at the same index.
+    let ring_ref = &*ring;
+    std::thread::scope(|s| {
+        s.spawn(move || -> anyhow::Result<()> {
+            let mut other = IoUring::new(2)?;
+            assert_eq!(other.submitter().register_ring_fd()?, index);
+
+            let submitter = ring_ref.submitter();
+            assert_eq!(submitter.registered_ring_fd(), None);
+            let err = submitter.register_ring_fd().unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+            let err = submitter.unregister_ring_fd().unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+            let nop_e = opcode::Nop::new().build().user_data(0x44).into();
+            unsafe {
+                let queue = ring_ref.submission();
+                queue.sync();
+                queue.push(&nop_e).expect("queue is full");
+                queue.sync();
+            }
+            ring_ref.submit_and_wait(1)?;
+            assert!(other.completion().is_empty());
+            Ok(())
+        })
+        .join()
+        .unwrap()
+    })?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].user_data(), 0x44);
+    assert_eq!(ring.submitter().registered_ring_fd(), Some(index));
+
+    ring.submitter().unregister_ring_fd()?;
+    assert_eq!(ring.submitter().registered_ring_fd(), None);
+
+    let err = ring.submitter().unregister_ring_fd().unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+    // Of threads registering the ring at once, a single one succeeds.
+    let ring_ref = &*ring;
+    let barrier = std::sync::Barrier::new(4);
+    let registered = std::thread::scope(|s| {
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let barrier = &barrier;
+                s.spawn(move || {
+                    let submitter = ring_ref.submitter();
+                    let res = submitter.register_ring_fd();
+                    barrier.wait();
+                    if res.is_ok() {
+                        submitter.unregister_ring_fd().unwrap();
+                    }
+                    res
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(registered.iter().filter(|res| res.is_ok()).count(), 1);
+    for res in &registered {
+        if let Err(err) = res {
+            assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+        }
+    }
+    assert_eq!(ring.submitter().registered_ring_fd(), None);
+
+    Ok(())
+}
+
+pub fn test_register_napi<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    require!(
+        test;
+        test.probe.is_supported(opcode::Recv::CODE);
+        test.probe.is_supported(opcode::Send::CODE);
+    );
+
+    println!("test register_napi");
+
+    // IORING_REGISTER_NAPI was introduced in kernel 6.9.
+    let napi = Napi::new().busy_poll_timeout(100).prefer_busy_poll(true);
+    match ring.submitter().register_napi(napi) {
+        Ok(_) => {}
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+            println!("register_napi not supported, skipping");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    // Registering again returns the settings registered above.
+    let prev = ring
+        .submitter()
+        .register_napi(Napi::new().busy_poll_timeout(50))?;
+    assert_eq!(prev.get_busy_poll_timeout(), 100);
+    assert!(prev.get_prefer_busy_poll());
+    assert_eq!(prev.get_tracking(), NapiTracking::Dynamic);
+
+    // Exchange a datagram over loopback while busy polling.
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    socket.connect(socket.local_addr()?)?;
+    let fd = types::Fd(socket.as_raw_fd());
+
+    let mut in_buf = [0; 16];
+    let out_buf = b"napi";
+
+    let recv_e = opcode::Recv::new(fd, in_buf.as_mut_ptr(), in_buf.len() as _)
+        .build()
+        .user_data(1)
+        .into();
+    let send_e = opcode::Send::new(fd, out_buf.as_ptr(), out_buf.len() as _)
+        .build()
+        .user_data(2)
+        .into();
+
+    unsafe {
+        let queue = ring.submission();
+        queue.push(&recv_e).expect("queue is full");
+        queue.push(&send_e).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(2)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+
+    assert_eq!(cqes.len(), 2);
+    for cqe in cqes {
+        assert_eq!(cqe.result(), out_buf.len() as i32);
+    }
+    assert_eq!(&in_buf[..out_buf.len()], out_buf);
+
+    let prev = ring.submitter().unregister_napi()?;
+    assert_eq!(prev.get_busy_poll_timeout(), 50);
+    assert!(!prev.get_prefer_busy_poll());
+
+    // Busy polling is disabled now.
+    let prev = ring.submitter().unregister_napi()?;
+    assert_eq!(prev.get_busy_poll_timeout(), 0);
+
+    Ok(())
+}
@@ -2,14 +2,16 @@
 // The entry point in this file can be found by searching for 'pub'.
 
 use crate::Test;
+use io_uring::buf_ring::BufRing;
 use io_uring::types;
 use io_uring::types::BufRingEntry;
 use io_uring::{cqueue, opcode, squeue, IoUring};
 
 use std::cell::Cell;
 use std::fmt;
-use std::io;
+use std::io::{self, Write};
 use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
 use std::ptr;
 use std::rc::Rc;
 use std::sync::atomic::{self, AtomicU16};
@@ -712,6 +714,55 @@ fn buf_ring_play<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     Ok(())
 }
 
+// Check that the head reported by the kernel follows the buffers it consumes.
+fn buf_ring_head<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    _test: &Test,
+) -> io::Result<()> {
+    let text = b"The quick brown fox jumps over the lazy dog.";
+    let len = text.len() as u32;
+
+    let buf_ring = Builder::new(889)
+        .ring_entries(4)
+        .buf_cnt(4)
+        .buf_len(128)
+        .build()?;
+
+    buf_ring.rc.register(ring)?;
+
+    // IORING_REGISTER_PBUF_STATUS was introduced in kernel 6.8.
+    match ring.submitter().buf_ring_head(889) {
+        Ok(head) => assert_eq!(head, 0),
+        Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => {
+            println!("buf_ring_head not supported, skipping");
+            return buf_ring.rc.unregister(ring);
+        }
+        Err(err) => return Err(err),
+    }
+
+    let fd = tempfile::tempfile()?;
+    let fd = types::Fd(fd.as_raw_fd());
+    write_text_to_file(ring, fd, text)?;
+
+    let buf0 = buf_ring_read(ring, &buf_ring, fd, len)?;
+    assert_eq!(ring.submitter().buf_ring_head(889)?, 1);
+    let buf1 = buf_ring_read(ring, &buf_ring, fd, len)?;
+    assert_eq!(ring.submitter().buf_ring_head(889)?, 2);
+
+    // Returning buffers moves the tail, not the head.
+    std::mem::drop(buf0);
+    std::mem::drop(buf1);
+    assert_eq!(ring.submitter().buf_ring_head(889)?, 2);
+
+    // An unknown group is rejected.
+    let err = ring.submitter().buf_ring_head(890).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+
+    buf_ring.rc.unregister(ring)?;
+
+    Ok(())
+}
+
 pub fn test_register_buf_ring<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     ring: &mut IoUring<S, C>,
     test: &Test,
@@ -733,5 +784,263 @@ pub fn test_register_buf_ring<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
 
     buf_ring_play(ring, test)?;
 
+    buf_ring_head(ring, test)?;
+
+    Ok(())
+}
+
+// Read from the start of the file into a buffer selected from the group, syncing the queue.
+fn buf_select_read<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    bgid: u16,
+    fd: types::Fd,
+) -> io::Result<(i32, u32)> {
+    let read_e = opcode::Read::new(fd, ptr::null_mut(), 128)
+        .offset(0)
+        .buf_group(bgid)
+        .build()
+        .user_data(0x03)
+        .flags(squeue::Flags::BUFFER_SELECT);
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue.push(&read_e.into()).expect("queue is full");
+        queue.sync();
+    }
+    ring.submit_and_wait(1)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].user_data(), 0x03);
+    Ok((cqes[0].result(), cqes[0].flags()))
+}
+
+pub fn test_buf_ring<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> io::Result<()> {
+    // register_buf_ring was introduced in kernel 5.19, as was the opcode for UringCmd16.
+    require!(
+        test;
+        test.probe.is_supported(opcode::UringCmd16::CODE);
+    );
+
+    println!("test buf_ring");
+
+    let text = b"The quick brown fox jumps over the lazy dog.";
+    let mut file = tempfile::tempfile()?;
+    file.write_all(text)?;
+    let fd = types::Fd(file.as_raw_fd());
+
+    let buf_ring = BufRing::builder(777)
+        .ring_entries(2)
+        .buf_len(128)
+        .build(&ring.submitter())?;
+    assert_eq!(buf_ring.ring_entries(), 2);
+    assert_eq!(buf_ring.buf_cnt(), 2);
+
+    let (res, flags) = buf_select_read(ring, 777, fd)?;
+    let buf0 = buf_ring.get(flags, res as usize).unwrap();
+    let (res, flags) = buf_select_read(ring, 777, fd)?;
+    let buf1 = buf_ring.get(flags, res as usize).unwrap();
+    assert_eq!(&*buf0, text);
+    assert_eq!(&*buf1, text);
+    assert_ne!(buf0.bid(), buf1.bid());
+    assert_eq!(buf0.capacity(), 128);
+
+    // A buffer cannot be taken twice.
+    assert!(buf_ring.get(flags, res as usize).is_none());
+
+    // The ring is empty until a buffer is dropped.
+    let (res, _) = buf_select_read(ring, 777, fd)?;
+    assert_eq!(res, -libc::ENOBUFS);
+
+    let bid = buf1.bid();
+    drop(buf1);
+    let (res, flags) = buf_select_read(ring, 777, fd)?;
+    let buf2 = buf_ring.get(flags, res as usize).unwrap();
+    assert_eq!(buf2.bid(), bid);
+    assert_eq!(&*buf2, text);
+
+    drop(buf0);
+    drop(buf2);
+
+    // A buffer picked by the kernel is in flight until its completion is handled.
+    // IORING_REGISTER_PBUF_STATUS was introduced in 6.8.
+    if buf_ring.in_flight().is_ok() {
+        assert_eq!(buf_ring.in_flight()?, 0);
+        let (res, flags) = buf_select_read(ring, 777, fd)?;
+        assert_eq!(buf_ring.in_flight()?, 1);
+        let buf = buf_ring.get(flags, res as usize).unwrap();
+        assert_eq!(buf_ring.in_flight()?, 0);
+        drop(buf);
+
+        // Dropping the ring with a buffer in flight leaks the buffers.
+        buf_select_read(ring, 777, fd)?;
+        assert_eq!(buf_ring.in_flight()?, 1);
+    }
+
+    // Dropping the ring unregisters the group, so it can be registered again.
+    drop(buf_ring);
+    let buf_ring = BufRing::builder(777).build(&ring.submitter())?;
+    assert_eq!(buf_ring.buf_cnt(), 128);
+
+    // Unregistering explicitly releases the buffers on any kernel.
+    assert!(unsafe { buf_ring.unregister() }.is_ok());
+    let buf_ring = BufRing::builder(777).build(&ring.submitter())?;
+    drop(buf_ring);
+
+    Ok(())
+}
+
+pub fn test_buf_ring_mmap<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> io::Result<()> {
+    // Buffer rings need kernel 5.19, but IOU_PBUF_RING_MMAP was only introduced in 6.4,
+    // which is checked by trying to register.
+    require!(
+        test;
+        test.probe.is_supported(opcode::UringCmd16::CODE);
+    );
+
+    println!("test buf_ring_mmap");
+
+    let mapped = match ring.submitter().register_buf_ring_mmap(4, 778, 0) {
+        Ok(mapped) => mapped,
+        Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => {
+            println!("register_buf_ring_mmap not supported, skipping");
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    };
+
+    // The kernel hands out a zeroed ring.
+    assert_eq!(mapped.entries(), 4);
+    let entries = unsafe { std::slice::from_raw_parts(mapped.as_mut_ptr(), 4) };
+    assert!(entries
+        .iter()
+        .all(|entry| entry.addr() == 0 && entry.len() == 0));
+    drop(mapped);
+    ring.submitter().unregister_buf_ring(778)?;
+
+    let text = b"The quick brown fox jumps over the lazy dog.";
+    let mut file = tempfile::tempfile()?;
+    file.write_all(text)?;
+    let fd = types::Fd(file.as_raw_fd());
+
+    let buf_ring = BufRing::builder(778)
+        .ring_entries(2)
+        .buf_len(128)
+        .kernel_alloc()
+        .build(&ring.submitter())?;
+
+    for _ in 0..4 {
+        let (res, flags) = buf_select_read(ring, 778, fd)?;
+        let buf = buf_ring.get(flags, res as usize).unwrap();
+        assert_eq!(&*buf, text);
+    }
+
+    Ok(())
+}
+
+pub fn test_buf_ring_incremental<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> io::Result<()> {
+    // Buffer rings need kernel 5.19, but IOU_PBUF_RING_INC was only introduced in 6.12,
+    // which is checked by trying to register.
+    require!(
+        test;
+        test.probe.is_supported(opcode::UringCmd16::CODE);
+    );
+
+    println!("test buf_ring_incremental");
+
+    // Send the text, then receive it into a buffer selected from the group.
+    fn send_recv<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+        ring: &mut IoUring<S, C>,
+        tx: &mut UnixStream,
+        rx: &UnixStream,
+        text: &[u8],
+    ) -> io::Result<(i32, u32)> {
+        tx.write_all(text)?;
+
+        let recv_e = opcode::Recv::new(types::Fd(rx.as_raw_fd()), ptr::null_mut(), 128)
+            .buf_group(780)
+            .build()
+            .user_data(0x04)
+            .flags(squeue::Flags::BUFFER_SELECT);
+
+        unsafe {
+            let queue = ring.submission();
+            queue.sync();
+            queue.push(&recv_e.into()).expect("queue is full");
+            queue.sync();
+        }
+        ring.submit_and_wait(1)?;
+
+        let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+        assert_eq!(cqes.len(), 1);
+        assert_eq!(cqes[0].user_data(), 0x04);
+        Ok((cqes[0].result(), cqes[0].flags()))
+    }
+
+    let text = b"The quick brown fox jumps over the lazy dog.";
+
+    // A single buffer with room for two and a bit receives.
+    let buf_len = text.len() * 2 + 10;
+    let buf_ring = match BufRing::builder(780)
+        .ring_entries(1)
+        .buf_len(buf_len)
+        .incremental()
+        .build(&ring.submitter())
+    {
+        Ok(buf_ring) => buf_ring,
+        Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => {
+            println!("incremental buf_ring not supported, skipping");
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    };
+
+    // The incremental mode only applies to pollable files.
+    let (mut tx, rx) = UnixStream::pair()?;
+
+    // The first two receives share the buffer, each getting its own range.
+    let (res, flags) = send_recv(ring, &mut tx, &rx, text)?;
+    assert!(cqueue::buffer_more(flags));
+    let buf0 = buf_ring.get(flags, res as usize).unwrap();
+    let (res, flags) = send_recv(ring, &mut tx, &rx, text)?;
+    assert!(cqueue::buffer_more(flags));
+    let buf1 = buf_ring.get(flags, res as usize).unwrap();
+
+    assert_eq!(buf0.bid(), buf1.bid());
+    assert_eq!(buf0.offset(), 0);
+    assert_eq!(buf1.offset(), text.len());
+    assert_eq!(&*buf0, text);
+    assert_eq!(&*buf1, text);
+
+    // The last receive fills what remains, after which the kernel gives up the buffer.
+    let (res, flags) = send_recv(ring, &mut tx, &rx, &text[..10])?;
+    assert_eq!(res, 10);
+    assert!(!cqueue::buffer_more(flags));
+    let buf2 = buf_ring.get(flags, res as usize).unwrap();
+    assert_eq!(buf2.offset(), text.len() * 2);
+    assert_eq!(&*buf2, &text[..10]);
+
+    // The buffer only goes back to the ring once every range is dropped.
+    drop(buf0);
+    drop(buf2);
+    let (res, _) = send_recv(ring, &mut tx, &rx, text)?;
+    assert_eq!(res, -libc::ENOBUFS);
+
+    drop(buf1);
+    let (res, flags) = send_recv(ring, &mut tx, &rx, &[])?;
+    let buf3 = buf_ring.get(flags, res as usize).unwrap();
+    assert_eq!(buf3.offset(), 0);
+    assert_eq!(&*buf3, text);
+
     Ok(())
 }
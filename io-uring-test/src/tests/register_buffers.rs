@@ -349,3 +349,93 @@ fn check_only_timeout<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
 
     Err(anyhow::anyhow!("unexpected completion queue entry"))
 }
+
+pub fn test_clone_buffers<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    require!(
+        test;
+        test.probe.is_supported(WriteFixed::CODE);
+    );
+
+    println!("test clone_buffers");
+
+    const BUF_SIZE: usize = 16;
+
+    let mut bufs = [[b'a'; BUF_SIZE], [b'b'; BUF_SIZE]];
+    let iovecs: Vec<iovec> = bufs
+        .iter_mut()
+        .map(|buf| iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        })
+        .collect();
+
+    let src = IoUring::<S, C>::builder().build(2)?;
+
+    // Safety: `bufs` outlives both rings' registrations, which are removed at the end of this
+    // function or when `src` is dropped.
+    unsafe { src.submitter().register_buffers(&iovecs)? };
+
+    // Cloning requires the destination table to be empty.
+    let _ = ring.submitter().unregister_buffers();
+
+    // IORING_REGISTER_CLONE_BUFFERS was introduced in kernel 6.12.
+    match ring
+        .submitter()
+        .clone_buffers_from(&src.submitter(), .., 0, false)
+    {
+        Ok(()) => {}
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+            println!("clone_buffers not supported, skipping");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    // Without replacing, the now populated table is not touched.
+    let err = ring
+        .submitter()
+        .clone_buffers_from(&src.submitter(), .., 0, false)
+        .unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EBUSY));
+
+    // Replace buffer 0 with buffer 1 of the source ring.
+    ring.submitter()
+        .clone_buffers_from(&src.submitter(), 1..2, 0, true)?;
+
+    // Both indices now refer to the second buffer, which is written out twice.
+    let file = tempfile::tempfile()?;
+    let fd = Fd(file.as_raw_fd());
+
+    unsafe {
+        let queue = ring.submission();
+        for index in 0..2 {
+            let write_e = WriteFixed::new(fd, bufs[1].as_ptr(), BUF_SIZE as _, index)
+                .offset((index as usize * BUF_SIZE) as _)
+                .build()
+                .user_data(index as _)
+                .into();
+            queue.push(&write_e).expect("queue is full");
+        }
+        queue.sync();
+    }
+
+    ring.submit_and_wait(2)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+
+    assert_eq!(cqes.len(), 2);
+    for cqe in cqes {
+        assert_eq!(cqe.result(), BUF_SIZE as i32);
+    }
+
+    let mut content = Vec::new();
+    io::Read::read_to_end(&mut &file, &mut content)?;
+    assert_eq!(content, [b'b'; 2 * BUF_SIZE]);
+
+    ring.submitter().unregister_buffers()?;
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use crate::Test;
+use io_uring::runtime::Reactor;
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+use std::cell::RefCell;
+use std::future::Future;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Wake, Waker};
+
+pub fn test_runtime_reactor<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    require!(
+        test;
+        test.probe.is_supported(opcode::Recv::CODE);
+    );
+
+    println!("test runtime_reactor");
+
+    let reactor = Reactor::new(IoUring::<S, C>::builder().build(8)?);
+
+    // single completions, awaited in any order
+
+    let (mut tx, rx) = UnixStream::pair()?;
+    let mut buf = [0u8; 16];
+
+    let recv_e = opcode::Recv::new(types::Fd(rx.as_raw_fd()), buf.as_mut_ptr(), buf.len() as _);
+    let recv = unsafe { reactor.push(recv_e.build())? };
+    let nop = unsafe { reactor.push(opcode::Nop::new().build())? };
+
+    let n = reactor.block_on(async {
+        let cqe: cqueue::Entry = nop.await.into();
+        assert_eq!(cqe.result(), 0);
+
+        tx.write_all(b"ping").unwrap();
+
+        let cqe: cqueue::Entry = recv.await.into();
+        cqe.result()
+    })?;
+    assert_eq!(n, 4);
+    assert_eq!(&buf[..4], b"ping");
+
+    // multishot completions as a stream
+
+    let ts = types::Timespec::new().nsec(10_000_000);
+    let timeout_e = opcode::Timeout::new(&ts)
+        .count(3)
+        .flags(types::TimeoutFlags::MULTISHOT);
+    let mut timeouts = unsafe { reactor.push_multishot(timeout_e.build())? };
+
+    let results = reactor.block_on(async {
+        let mut results = Vec::new();
+        while let Some(cqe) = timeouts.next().await {
+            let cqe: cqueue::Entry = cqe.into();
+            results.push((cqe.result(), cqueue::more(cqe.flags())));
+        }
+        results
+    })?;
+    assert_eq!(
+        results,
+        [
+            (-libc::ETIME, true),
+            (-libc::ETIME, true),
+            (-libc::ETIME, false)
+        ]
+    );
+
+    // dropped futures don't get in the way of later requests
+
+    let mut buf = [0u8; 16];
+    let recv_e = opcode::Recv::new(types::Fd(rx.as_raw_fd()), buf.as_mut_ptr(), buf.len() as _);
+    drop(unsafe { reactor.push(recv_e.build())? });
+    tx.write_all(b"pong")?;
+
+    let nop = unsafe { reactor.push(opcode::Nop::new().build())? };
+    let cqe: cqueue::Entry = reactor.block_on(nop)?.into();
+    assert_eq!(cqe.result(), 0);
+    reactor.wait(0)?;
+    assert_eq!(&buf[..4], b"pong");
+
+    Ok(())
+}
+
+pub fn test_runtime_foreign_cqe<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    require!(
+        test;
+        test.probe.is_supported(opcode::MsgRingData::CODE);
+        test.probe.is_supported(opcode::Timeout::CODE);
+    );
+
+    println!("test runtime_foreign_cqe");
+
+    let ring = IoUring::<S, C>::builder().build(8)?;
+    let fd = types::Fd(ring.as_raw_fd());
+    let reactor = Reactor::new(ring);
+    let mut other = IoUring::new(8)?;
+
+    // the slot of a completed request is reused by the next one, while completions with its
+    // bare key or its previous generation are posted by another ring
+
+    let nop = unsafe { reactor.push(opcode::Nop::new().build())? };
+    reactor.block_on(nop)?;
+
+    let ts = types::Timespec::new().nsec(50_000_000);
+    let timeout = unsafe { reactor.push(opcode::Timeout::new(&ts).build())? };
+
+    let sq = other.submission();
+    sq.sync();
+    for user_data in [0, 1 << 32] {
+        let entry = opcode::MsgRingData::new(fd, 7, user_data, None)
+            .build()
+            .user_data(0x01);
+        unsafe { sq.push(&entry).expect("queue is full") };
+    }
+    sq.sync();
+    other.submit_and_wait(2)?;
+    for cqe in other.completion() {
+        assert_eq!(cqe.result(), 0);
+    }
+
+    let cqe: cqueue::Entry = reactor.block_on(timeout)?.into();
+    assert_eq!(cqe.result(), -libc::ETIME);
+
+    // a task woken by the reactor may push requests from its waker
+
+    thread_local! {
+        static REACTOR: RefCell<Option<Reactor>> = const { RefCell::new(None) };
+    }
+
+    struct PushOnWake(AtomicBool);
+
+    impl Wake for PushOnWake {
+        fn wake(self: Arc<Self>) {
+            REACTOR.with(|reactor| {
+                let reactor = reactor.borrow();
+                let reactor = reactor.as_ref().unwrap();
+                drop(unsafe { reactor.push(opcode::Nop::new().build()) }.expect("queue is full"));
+            });
+            self.0.store(true, Ordering::Release);
+        }
+    }
+
+    let reactor = Reactor::new(IoUring::new(8)?);
+    REACTOR.with(|cell| *cell.borrow_mut() = Some(reactor.clone()));
+
+    let flag = Arc::new(PushOnWake(AtomicBool::new(false)));
+    let waker = Waker::from(flag.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let mut nop = unsafe { reactor.push(opcode::Nop::new().build())? };
+    assert!(Pin::new(&mut nop).poll(&mut cx).is_pending());
+    reactor.wait(1)?;
+    assert!(flag.0.load(Ordering::Acquire));
+    assert!(Pin::new(&mut nop).poll(&mut cx).is_ready());
+
+    // the request pushed by the waker completes as well
+    reactor.wait(1)?;
+    REACTOR.with(|cell| cell.borrow_mut().take());
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use crate::Test;
+use io_uring::safe::{self, Ring};
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::time::Instant;
+
+pub fn test_safe_ring<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    require!(
+        test;
+        test.probe.is_supported(opcode::Write::CODE);
+        test.probe.is_supported(opcode::Read::CODE);
+    );
+
+    println!("test safe_ring");
+
+    let mut ring = Ring::new(IoUring::<S, C>::builder().build(8)?);
+
+    let file = tempfile::tempfile()?;
+    let fd = types::Fd(file.as_raw_fd());
+    let text = b"The quick brown fox jumps over the lazy dog.";
+
+    // write, then read back into the spare capacity of a buffer
+
+    let write_id = ring.push(safe::Write::new(fd, text.to_vec(), 0)).unwrap();
+    ring.submit_and_wait(1)?;
+
+    let mut cqes = ring.completions();
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].id(), write_id);
+    assert_eq!(cqes[0].result(), text.len() as i32);
+    assert!(cqes[0].take::<safe::Read>().is_none());
+    assert_eq!(cqes[0].take::<safe::Write>().unwrap().into_buf(), text);
+    assert!(cqes[0].take::<safe::Write>().is_none());
+
+    let mut buf = Vec::with_capacity(64);
+    buf.extend_from_slice(b">> ");
+    ring.push(safe::Read::new(fd, buf, 0)).unwrap();
+    ring.push(safe::Nop).unwrap();
+    assert_eq!(ring.in_flight(), 2);
+    ring.submit_and_wait(2)?;
+
+    let mut cqes = ring.completions();
+    assert_eq!(cqes.len(), 2);
+    assert_eq!(ring.in_flight(), 0);
+    let buf = cqes[0].take::<safe::Read>().unwrap().into_buf();
+    assert_eq!(&buf[..3], b">> ");
+    assert_eq!(&buf[3..], text);
+    assert!(cqes[1].take::<safe::Nop>().is_some());
+
+    // operations in flight are cancelled when the ring is dropped
+
+    let (mut tx, rx) = UnixStream::pair()?;
+    let rx_fd = types::Fd(rx.as_raw_fd());
+
+    ring.push(safe::Recv::new(rx_fd, Vec::with_capacity(64)))
+        .unwrap();
+    let ts = types::Timespec::new().sec(10);
+    ring.push(safe::Timeout::new(ts)).unwrap();
+    ring.submit()?;
+
+    let start = Instant::now();
+    drop(ring);
+    assert!(start.elapsed().as_secs() < 5);
+
+    // the socket is still usable afterwards
+    tx.write_all(b"ping")?;
+
+    let mut ring = Ring::new(IoUring::<S, C>::builder().build(8)?);
+    ring.push(safe::Recv::new(rx_fd, Vec::with_capacity(64)))
+        .unwrap();
+    ring.push(safe::Close::new(tx)).unwrap();
+    ring.submit_and_wait(2)?;
+
+    let mut cqes = ring.completions();
+    assert_eq!(cqes.len(), 2);
+    for cqe in cqes.iter_mut() {
+        assert!(cqe.result() >= 0, "{:?}", cqe);
+        if let Some(recv) = cqe.take::<safe::Recv>() {
+            assert_eq!(recv.into_buf(), b"ping");
+        }
+    }
+
+    Ok(())
+}
+
+pub fn test_safe_ring_foreign_cqe<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    require!(
+        test;
+        test.probe.is_supported(opcode::MsgRingData::CODE);
+        test.probe.is_supported(opcode::Timeout::CODE);
+    );
+
+    println!("test safe_ring_foreign_cqe");
+
+    let mut ring = Ring::new(IoUring::<S, C>::builder().build(8)?);
+    let mut other = IoUring::new(8)?;
+
+    // the slot of a completed operation is reused by the next one
+
+    let nop_id = ring.push(safe::Nop).unwrap();
+    ring.submit_and_wait(1)?;
+    let cqes = ring.completions();
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].id(), nop_id);
+
+    let ts = types::Timespec::new().sec(10);
+    let timeout_id = ring.push(safe::Timeout::new(ts)).unwrap();
+    assert_ne!(timeout_id, nop_id);
+    ring.submit()?;
+
+    // completions posted by another ring, with the bare slot and the identifier of the
+    // previous operation, must not release the timeout
+
+    let sq = other.submission();
+    sq.sync();
+    for user_data in [timeout_id.user_data() as u32 as u64, nop_id.user_data()] {
+        let entry = opcode::MsgRingData::new(types::Fd(ring.as_raw_fd()), 0, user_data, None)
+            .build()
+            .user_data(0x01);
+        unsafe { sq.push(&entry).expect("queue is full") };
+    }
+    sq.sync();
+    other.submit_and_wait(2)?;
+    for cqe in other.completion() {
+        assert_eq!(cqe.result(), 0);
+    }
+
+    ring.submit_and_wait(2)?;
+    let mut cqes = ring.completions();
+    assert_eq!(cqes.len(), 2);
+    for cqe in cqes.iter_mut() {
+        assert_ne!(cqe.id(), timeout_id);
+        assert!(cqe.take::<safe::Timeout>().is_none());
+        assert!(cqe.take::<safe::Nop>().is_none());
+    }
+    assert_eq!(ring.in_flight(), 1);
+
+    // the timeout is still cancelled when the ring is dropped
+
+    let start = Instant::now();
+    drop(ring);
+    assert!(start.elapsed().as_secs() < 5);
+
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use crate::Test;
 use io_uring::{cqueue, opcode, squeue, types, IoUring};
-use std::time::Instant;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
 
 pub fn test_timeout<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     ring: &mut IoUring<S, C>,
@@ -434,6 +436,237 @@ pub fn test_timeout_submit_args_min_wait<S: squeue::EntryMarker, C: cqueue::Entr
     Ok(())
 }
 
+pub fn test_timeout_submit_args_abs<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    require! {
+        test;
+        ring.params().is_feature_ext_arg();
+    };
+
+    println!("test timeout_submit_args_abs");
+
+    // IORING_REGISTER_CLOCK and IORING_ENTER_ABS_TIMER were introduced in kernel 6.12.
+    match ring.submitter().register_clock(types::ClockId::Boottime) {
+        Ok(()) => {}
+        Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => {
+            println!("register_clock not supported, skipping");
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    let ret = unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut now) };
+
+    assert_eq!(ret, 0);
+
+    let ts = types::Timespec::new()
+        .sec(now.tv_sec as u64 + 1)
+        .nsec(now.tv_nsec as u32);
+    let args = types::SubmitArgs::new().timespec(&ts).absolute();
+
+    let start = Instant::now();
+    match ring.submitter().submit_with_args(1, &args) {
+        Ok(_) => panic!(),
+        Err(ref err) if err.raw_os_error() == Some(libc::ETIME) => (),
+        Err(err) => return Err(err.into()),
+    }
+    assert!(start.elapsed().as_millis() >= 500);
+    assert!(start.elapsed().as_millis() < 2000);
+
+    // A deadline in the past expires immediately.
+    let start = Instant::now();
+    match ring.submitter().submit_with_args(1, &args) {
+        Ok(_) => panic!(),
+        Err(ref err) if err.raw_os_error() == Some(libc::ETIME) => (),
+        Err(err) => return Err(err.into()),
+    }
+    assert!(start.elapsed().as_millis() < 100);
+
+    assert!(ring.completion().next().is_none());
+
+    ring.submitter().register_clock(types::ClockId::Monotonic)?;
+
+    Ok(())
+}
+
+pub fn test_timeout_registered_wait<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    test: &Test,
+) -> anyhow::Result<()> {
+    require! {
+        test;
+    };
+
+    println!("test timeout_registered_wait");
+
+    // The wait region must be registered before the rings are enabled.
+    let mut ring = IoUring::<S, C>::builder().setup_r_disabled().build(8)?;
+
+    // IORING_REGISTER_MEM_REGION was introduced in kernel 6.13.
+    let mut region = match ring.submitter().register_wait_region(2) {
+        Ok(region) => region,
+        Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => {
+            println!("register_wait_region not supported, skipping");
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    ring.submitter().register_enable_rings()?;
+
+    // Slot 0 times out, slot 1 waits without a timeout.
+    let ts = types::Timespec::new().nsec(200_000_000);
+    region.slots_mut()[0].set_timeout(Some(ts));
+
+    // timeout
+
+    let start = Instant::now();
+    match ring.submitter().submit_with_registered_wait(1, 0) {
+        Ok(_) => panic!(),
+        Err(ref err) if err.raw_os_error() == Some(libc::ETIME) => (),
+        Err(err) => return Err(err.into()),
+    }
+    assert!(start.elapsed().as_millis() >= 200);
+
+    // no timeout
+
+    let nop_e = opcode::Nop::new().build().user_data(0x1e).into();
+
+    unsafe {
+        let queue = ring.submission();
+        queue.push(&nop_e).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submitter().submit_with_registered_wait(1, 1)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].user_data(), 0x1e);
+    assert_eq!(cqes[0].result(), 0);
+
+    // out of bounds
+
+    let index = region.slots().len();
+    let err = ring
+        .submitter()
+        .submit_with_registered_wait(1, index)
+        .unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EFAULT));
+
+    Ok(())
+}
+
+pub fn test_timeout_wait<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    require!(
+        test;
+        test.probe.is_supported(opcode::Timeout::CODE);
+    );
+
+    println!("test timeout_wait");
+
+    fn completions<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+        ring: &mut IoUring<S, C>,
+    ) -> Vec<cqueue::Entry> {
+        ring.completion()
+            .map(Into::<cqueue::Entry>::into)
+            .filter(|cqe| cqe.user_data() != types::WAIT_TIMEOUT_USER_DATA)
+            .collect()
+    }
+
+    // timeout
+
+    let start = Instant::now();
+    let status = ring.wait(1, Some(Duration::from_millis(200)))?;
+    assert_eq!(status, types::WaitStatus::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert!(completions(ring).is_empty());
+
+    // no timeout
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue
+            .push(&opcode::Nop::new().build().user_data(0x1e).into())
+            .expect("queue is full");
+        queue.sync();
+    }
+
+    let start = Instant::now();
+    let status = ring.wait(1, Some(Duration::from_secs(1)))?;
+    assert_eq!(status, types::WaitStatus::Ready);
+    assert_eq!(start.elapsed().as_secs(), 0);
+
+    let cqes = completions(ring);
+    assert_eq!(cqes.len(), 1);
+    assert_eq!(cqes[0].user_data(), 0x1e);
+
+    // minimum wait, after which a single completion is enough
+
+    if ring.params().is_feature_min_timeout() {
+        unsafe {
+            let queue = ring.submission();
+            queue.sync();
+            queue
+                .push(&opcode::Nop::new().build().user_data(0x1f).into())
+                .expect("queue is full");
+            queue.sync();
+        }
+
+        let start = Instant::now();
+        let status = ring.wait_min(2, Duration::from_millis(200), Some(Duration::from_secs(2)))?;
+        assert_eq!(status, types::WaitStatus::Ready);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(start.elapsed().as_secs(), 0);
+
+        let cqes = completions(ring);
+        assert_eq!(cqes.len(), 1);
+        assert_eq!(cqes[0].user_data(), 0x1f);
+    }
+
+    // the timeout of a previous wait is not counted by the next one
+
+    let status = ring.wait(1, Some(Duration::from_millis(10)))?;
+    assert_eq!(status, types::WaitStatus::TimedOut);
+
+    // a signal interrupts the wait
+
+    extern "C" fn handler(_: libc::c_int) {}
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as extern "C" fn(libc::c_int) as usize;
+        assert_eq!(
+            libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()),
+            0
+        );
+    }
+
+    let thread = unsafe { libc::pthread_self() };
+    let start = Instant::now();
+    let status = std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(Duration::from_millis(50));
+            unsafe { libc::pthread_kill(thread, libc::SIGUSR1) };
+        });
+        ring.wait(1, Some(Duration::from_secs(10)))
+    })?;
+    assert_eq!(status, types::WaitStatus::Interrupted);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(completions(ring).is_empty());
+
+    Ok(())
+}
+
 pub fn test_timeout_multishot<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
     ring: &mut IoUring<S, C>,
     test: &Test,
@@ -484,3 +717,190 @@ pub fn test_timeout_multishot<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
 
     Ok(())
 }
+
+pub fn test_timeout_link_chain<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    ring: &mut IoUring<S, C>,
+    test: &Test,
+) -> anyhow::Result<()> {
+    require!(
+        test;
+        test.probe.is_supported(opcode::LinkTimeout::CODE);
+        test.probe.is_supported(opcode::Recv::CODE);
+        test.probe.is_supported(opcode::AsyncCancel::CODE);
+    );
+
+    println!("test timeout_link_chain");
+
+    // all steps complete
+
+    let chain = squeue::Chain::new(0x20)
+        .push(opcode::Nop::new().build())
+        .push(opcode::Nop::new().build())
+        .push(opcode::Nop::new().build());
+    assert_eq!(chain.len(), 3);
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue.push_chain(&chain).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(3)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    assert_eq!(cqes.len(), 3);
+    assert!(cqes.iter().all(|cqe| cqe.result() == 0));
+    assert_eq!(chain.failure(&cqes), None);
+
+    // the first step fails, the rest of the chain is cancelled
+
+    let mut buf = [0u8; 16];
+    let chain = squeue::Chain::new(0x30)
+        .push(opcode::Read::new(types::Fd(-1), buf.as_mut_ptr(), buf.len() as _).build())
+        .push(opcode::Nop::new().build());
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue.push_chain(&chain).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(2)?;
+
+    let mut cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    cqes.sort_by_key(|cqe| cqe.user_data());
+    assert_eq!(cqes.len(), 2);
+    assert_eq!(cqes[0].user_data(), 0x30);
+    assert_eq!(cqes[0].result(), -libc::EBADF);
+    assert_eq!(cqes[1].user_data(), 0x31);
+    assert_eq!(cqes[1].result(), -libc::ECANCELED);
+    assert_eq!(
+        chain.failure(&cqes),
+        Some(squeue::ChainFailure::Failed {
+            step: 0,
+            result: -libc::EBADF
+        })
+    );
+
+    // a short read breaks the chain
+
+    let mut file = tempfile::tempfile()?;
+    file.write_all(b"test")?;
+    let chain = squeue::Chain::new(0x50)
+        .push(
+            opcode::Read::new(
+                types::Fd(file.as_raw_fd()),
+                buf.as_mut_ptr(),
+                buf.len() as _,
+            )
+            .offset(0)
+            .build(),
+        )
+        .push(opcode::Nop::new().build());
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue.push_chain(&chain).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(2)?;
+
+    let cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    assert_eq!(cqes.len(), 2);
+    assert_eq!(
+        chain.failure(&cqes),
+        Some(squeue::ChainFailure::Failed { step: 0, result: 4 })
+    );
+
+    // a step cancelled by another request is reported itself
+
+    let (rx, _tx) = std::os::unix::net::UnixStream::pair()?;
+    let chain = squeue::Chain::new(0x60)
+        .push(opcode::Nop::new().build())
+        .push(
+            opcode::Recv::new(types::Fd(rx.as_raw_fd()), buf.as_mut_ptr(), buf.len() as _).build(),
+        );
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue.push_chain(&chain).expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(1)?;
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue
+            .push(
+                &opcode::AsyncCancel::new(0x61)
+                    .build()
+                    .user_data(0x70)
+                    .into(),
+            )
+            .expect("queue is full");
+        queue.sync();
+    }
+
+    ring.submit_and_wait(3)?;
+
+    let cqes: Vec<cqueue::Entry> = ring
+        .completion()
+        .map(Into::into)
+        .filter(|cqe: &cqueue::Entry| cqe.user_data() != 0x70)
+        .collect();
+    assert_eq!(cqes.len(), 2);
+    assert_eq!(
+        chain.failure(&cqes),
+        Some(squeue::ChainFailure::Failed {
+            step: 1,
+            result: -libc::ECANCELED
+        })
+    );
+
+    // the second step times out
+
+    let (rx, _tx) = std::os::unix::net::UnixStream::pair()?;
+    let ts = types::Timespec::new().nsec(100_000_000);
+    let chain = squeue::Chain::new(0x40)
+        .push(opcode::Nop::new().build())
+        .push_with_timeout(
+            opcode::Recv::new(types::Fd(rx.as_raw_fd()), buf.as_mut_ptr(), buf.len() as _).build(),
+            &ts,
+        );
+    assert_eq!(chain.len(), 3);
+    assert_eq!(chain.user_data_of(1, false), Some(0x41));
+    assert_eq!(chain.user_data_of(1, true), Some(0x42));
+    assert_eq!(chain.user_data_of(0, true), None);
+
+    unsafe {
+        let queue = ring.submission();
+        queue.sync();
+        queue.push_chain(&chain).expect("queue is full");
+        queue.sync();
+    }
+
+    let start = Instant::now();
+    ring.submit_and_wait(3)?;
+
+    assert_eq!(start.elapsed().as_secs(), 0);
+
+    let mut cqes: Vec<cqueue::Entry> = ring.completion().map(Into::into).collect();
+    cqes.sort_by_key(|cqe| cqe.user_data());
+    assert_eq!(cqes.len(), 3);
+    assert_eq!(cqes[0].result(), 0);
+    assert_eq!(cqes[1].result(), -libc::ECANCELED);
+    assert_eq!(cqes[2].result(), -libc::ETIME);
+    assert_eq!(
+        chain.failure(&cqes),
+        Some(squeue::ChainFailure::TimedOut { step: 1 })
+    );
+
+    Ok(())
+}
@@ -0,0 +1,485 @@
+//! Provided buffer rings.
+//!
+//! A [`BufRing`] owns a ring of buffers registered with the kernel under a buffer group id.
+//! Requests using that group with [`BUFFER_SELECT`](crate::squeue::Flags::BUFFER_SELECT), or
+//! the multishot receive opcodes, pick a buffer from the ring and report its id in the
+//! completion flags. [`BufRing::get`] turns that id back into a [`Buf`], which returns the
+//! buffer to the ring when dropped.
+//!
+//! With [`Builder::incremental`], the kernel may fill a buffer in several completions, each
+//! with its own [`Buf`] covering the bytes it wrote. The buffer goes back to the ring once
+//! the kernel is done with it and all of these are dropped.
+//!
+//! Requests only pick buffers while the ring is registered, but a request that already picked
+//! one writes to it until it completes. Handing the group id to a request is therefore part of
+//! the `unsafe` contract of pushing its entry: the [`BufRing`] must outlive every request that
+//! may still write to one of its buffers, including armed multishot requests.
+
+use std::cell::Cell;
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::atomic::{self, AtomicU16};
+use std::{fmt, io, mem, ops, ptr, slice};
+
+use crate::register::execute;
+use crate::types::BufRingEntry;
+use crate::util::{cast_ptr, Mmap, OwnedFd};
+use crate::{cqueue, sys, Submitter};
+
+/// A ring of provided buffers, registered with an io_uring instance.
+///
+/// The ring is unregistered and its memory released when it is dropped. It keeps its own
+/// reference to the io_uring instance, so it may outlive the [`IoUring`](crate::IoUring) it
+/// was registered with.
+///
+/// The buffers are leaked instead of released if the kernel still holds any that it picked,
+/// see [`in_flight`](Self::in_flight), or if the ring cannot be unregistered. Before 6.8 the
+/// kernel cannot report this and the buffers are always leaked on drop; use
+/// [`unregister`](Self::unregister) to release them there.
+pub struct BufRing {
+    fd: OwnedFd,
+    bgid: u16,
+    mask: u16,
+    buf_len: usize,
+    ring: ManuallyDrop<Mmap>,
+    bufs: ManuallyDrop<Mmap>,
+    tail: Cell<u16>,
+    states: Vec<BufState>,
+}
+
+// Tracks a buffer across the completions that use it.
+struct BufState {
+    // Whether the buffer is in the ring, or kept by the kernel for incremental consumption.
+    kernel: Cell<bool>,
+    // The start of the next completion's data, for incremental consumption.
+    offset: Cell<usize>,
+    // The number of `Buf`s alive for this buffer.
+    refs: Cell<u32>,
+}
+
+/// Build a [`BufRing`].
+#[derive(Clone, Debug)]
+pub struct Builder {
+    bgid: u16,
+    ring_entries: u16,
+    buf_cnt: u16,
+    buf_len: usize,
+    flags: u16,
+    kernel_alloc: bool,
+    dontfork: bool,
+}
+
+/// A buffer picked by the kernel from a [`BufRing`].
+///
+/// The buffer is given back to the ring when this is dropped.
+pub struct Buf<'a> {
+    ring: &'a BufRing,
+    bid: u16,
+    offset: usize,
+    len: usize,
+}
+
+// SAFETY: the ring memory is only written through `&BufRing` by `Buf` drops, which cannot
+// happen from another thread since `BufRing` is not `Sync`.
+unsafe impl Send for BufRing {}
+
+impl BufRing {
+    /// Create a [`Builder`] for a ring registered under the buffer group `bgid`.
+    pub fn builder(bgid: u16) -> Builder {
+        Builder {
+            bgid,
+            ring_entries: 128,
+            buf_cnt: 0,
+            buf_len: 4096,
+            flags: 0,
+            kernel_alloc: false,
+            dontfork: false,
+        }
+    }
+
+    /// The buffer group id the ring is registered under.
+    pub fn bgid(&self) -> u16 {
+        self.bgid
+    }
+
+    /// The number of entries in the ring.
+    pub fn ring_entries(&self) -> u16 {
+        self.mask.wrapping_add(1)
+    }
+
+    /// The number of buffers owned by the ring.
+    pub fn buf_cnt(&self) -> u16 {
+        self.states.len() as u16
+    }
+
+    /// The capacity of each buffer.
+    pub fn buf_len(&self) -> usize {
+        self.buf_len
+    }
+
+    /// Take the buffer selected for a completion, given its flags and the number of bytes
+    /// written to the buffer.
+    ///
+    /// Returns `None` if no buffer was selected, or if the buffer id does not belong to this
+    /// ring or is already taken.
+    ///
+    /// For an [incremental](Builder::incremental) ring, the returned buffer covers the bytes
+    /// following those of the previous completion for the same buffer id, and the buffer is
+    /// kept by the kernel as long as [`cqueue::buffer_more`] is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data would go past the end of the buffer.
+    pub fn get(&self, flags: u32, len: usize) -> Option<Buf<'_>> {
+        let bid = cqueue::buffer_select(flags)?;
+        let state = self.states.get(bid as usize)?;
+        if !state.kernel.get() {
+            return None;
+        }
+
+        let offset = state.offset.get();
+        assert!(
+            len <= self.buf_len - offset,
+            "length exceeds buffer capacity"
+        );
+        if cqueue::buffer_more(flags) {
+            state.offset.set(offset + len);
+        } else {
+            state.offset.set(0);
+            state.kernel.set(false);
+        }
+        state.refs.set(state.refs.get() + 1);
+
+        Some(Buf {
+            ring: self,
+            bid,
+            offset,
+            len,
+        })
+    }
+
+    /// The number of buffers the kernel picked from the ring that were not yet taken with
+    /// [`get`](Self::get), either because a request is still using them or because their
+    /// completion was not handled.
+    ///
+    /// Available since 6.8.
+    pub fn in_flight(&self) -> io::Result<u16> {
+        let mut arg = sys::io_uring_buf_status {
+            buf_group: self.bgid as _,
+            ..Default::default()
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_PBUF_STATUS,
+            (&mut arg as *mut sys::io_uring_buf_status).cast(),
+            1,
+        )?;
+
+        // Buffers between the kernel head and the tail are still available, the others the
+        // ring gave to the kernel were picked.
+        let available = self.tail.get().wrapping_sub(arg.head as u16);
+        let kernel = self
+            .states
+            .iter()
+            .filter(|state| state.kernel.get())
+            .count();
+        Ok((kernel as u16).wrapping_sub(available))
+    }
+
+    /// Unregister the ring and release its buffers without checking whether the kernel still
+    /// holds any.
+    ///
+    /// Dropping the ring leaks the buffers when [`in_flight`](Self::in_flight) is not
+    /// available, that is before 6.8. This releases them on those kernels. The ring is given
+    /// back if it cannot be unregistered.
+    ///
+    /// # Safety
+    ///
+    /// No request that picked a buffer from the ring may still be in flight, including armed
+    /// multishot requests.
+    pub unsafe fn unregister(self) -> Result<(), Self> {
+        let mut ring = ManuallyDrop::new(self);
+        if ring.release(true) {
+            // The mappings are released, drop the remaining fields in place.
+            ptr::drop_in_place(&mut ring.fd);
+            ptr::drop_in_place(&mut ring.states);
+            Ok(())
+        } else {
+            Err(ManuallyDrop::into_inner(ring))
+        }
+    }
+
+    fn buf_ptr(&self, bid: u16) -> *mut u8 {
+        unsafe {
+            self.bufs
+                .as_mut_ptr()
+                .cast::<u8>()
+                .add(bid as usize * self.buf_len)
+        }
+    }
+
+    // Add the buffer at the local tail, without publishing it to the kernel.
+    fn push(&self, bid: u16) {
+        let tail = self.tail.get();
+        self.tail.set(tail.wrapping_add(1));
+
+        let entries = self.ring.as_mut_ptr().cast::<BufRingEntry>();
+        let entry = unsafe { &mut *entries.add((tail & self.mask) as usize) };
+        entry.set_addr(self.buf_ptr(bid) as _);
+        entry.set_len(self.buf_len as _);
+        entry.set_bid(bid);
+    }
+
+    // Clear the length of every entry, so a request picking one of them afterwards has no room
+    // to write into its buffer.
+    fn retract(&self) {
+        let entries = self.ring.as_mut_ptr().cast::<BufRingEntry>();
+        for i in 0..=self.mask as usize {
+            unsafe {
+                let entry = &mut *entries.add(i);
+                entry.set_len(0);
+            }
+        }
+        atomic::fence(atomic::Ordering::SeqCst);
+    }
+
+    // Unregister the ring, and release its memory if that succeeded. The buffers are leaked
+    // unless `free` is set.
+    unsafe fn release(&mut self, free: bool) -> bool {
+        let arg = sys::io_uring_buf_reg {
+            bgid: self.bgid,
+            ..Default::default()
+        };
+        let unregistered = execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_UNREGISTER_PBUF_RING,
+            cast_ptr::<sys::io_uring_buf_reg>(&arg).cast(),
+            1,
+        )
+        .is_ok();
+
+        if unregistered {
+            ManuallyDrop::drop(&mut self.ring);
+            if free {
+                ManuallyDrop::drop(&mut self.bufs);
+            }
+        }
+        unregistered
+    }
+
+    // Publish the local tail to the kernel.
+    fn sync(&self) {
+        unsafe {
+            let tail = BufRingEntry::tail(self.ring.as_mut_ptr().cast()) as *const AtomicU16;
+            (*tail).store(self.tail.get(), atomic::Ordering::Release);
+        }
+    }
+}
+
+impl Drop for BufRing {
+    fn drop(&mut self) {
+        // A request that picks a buffer after the lengths are cleared reads nothing into it,
+        // so the status taken after that covers every pick that may still write. If it cannot
+        // be read, the buffers are leaked.
+        self.retract();
+        let idle = matches!(self.in_flight(), Ok(0));
+
+        // SAFETY: the buffers are only released if no picked buffer is left.
+        unsafe {
+            self.release(idle);
+        }
+    }
+}
+
+impl fmt::Debug for BufRing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufRing")
+            .field("bgid", &self.bgid)
+            .field("ring_entries", &self.ring_entries())
+            .field("buf_cnt", &self.buf_cnt())
+            .field("buf_len", &self.buf_len)
+            .finish()
+    }
+}
+
+impl Builder {
+    /// The number of entries in the ring, rounded up to a power of two.
+    ///
+    /// The kernel limits this to 32768. Defaults to 128.
+    pub fn ring_entries(&mut self, ring_entries: u16) -> &mut Self {
+        self.ring_entries = ring_entries;
+        self
+    }
+
+    /// The number of buffers to allocate, at most the number of ring entries.
+    ///
+    /// Defaults to one buffer per ring entry.
+    pub fn buf_cnt(&mut self, buf_cnt: u16) -> &mut Self {
+        self.buf_cnt = buf_cnt;
+        self
+    }
+
+    /// The capacity of each buffer. Defaults to 4096.
+    pub fn buf_len(&mut self, buf_len: usize) -> &mut Self {
+        self.buf_len = buf_len;
+        self
+    }
+
+    /// Flags passed to
+    /// [`Submitter::register_buf_ring_with_flags`](crate::Submitter::register_buf_ring_with_flags).
+    pub fn flags(&mut self, flags: u16) -> &mut Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Let the kernel consume buffers incrementally with the `IOU_PBUF_RING_INC` flag, so a
+    /// buffer can be shared by several completions until it is full.
+    ///
+    /// The kernel only reports partial consumption for pollable files such as sockets and
+    /// pipes, so the ring must not be used for reads from other files.
+    ///
+    /// Available since 6.12.
+    pub fn incremental(&mut self) -> &mut Self {
+        self.flags |= sys::IOU_PBUF_RING_INC as u16;
+        self
+    }
+
+    /// Let the kernel allocate the ring memory, see
+    /// [`Submitter::register_buf_ring_mmap`](crate::Submitter::register_buf_ring_mmap).
+    /// The buffers are still allocated by the application.
+    pub fn kernel_alloc(&mut self) -> &mut Self {
+        self.kernel_alloc = true;
+        self
+    }
+
+    /// Do not make the ring and buffers accessible by child processes after a `fork`.
+    pub fn dontfork(&mut self) -> &mut Self {
+        self.dontfork = true;
+        self
+    }
+
+    /// Allocate the ring and its buffers, and register it with the io_uring instance of
+    /// `submitter`. All buffers are initially available to the kernel.
+    pub fn build(&self, submitter: &Submitter<'_>) -> io::Result<BufRing> {
+        let ring_entries = self.ring_entries.max(self.buf_cnt);
+        let buf_cnt = if self.buf_cnt == 0 {
+            ring_entries
+        } else {
+            self.buf_cnt
+        };
+        if ring_entries == 0 || ring_entries > 1 << 15 || self.buf_len == 0 {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+        let ring_entries = ring_entries.next_power_of_two();
+        let bufs_len = (buf_cnt as usize)
+            .checked_mul(self.buf_len)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+        let fd = match unsafe { libc::fcntl(submitter.fd(), libc::F_DUPFD_CLOEXEC, 0) } {
+            -1 => return Err(io::Error::last_os_error()),
+            fd => unsafe { OwnedFd::from_raw_fd(fd) },
+        };
+        let bufs = Mmap::new_anon(bufs_len)?;
+        let ring = if self.kernel_alloc {
+            submitter
+                .register_buf_ring_mmap(ring_entries, self.bgid, self.flags)?
+                .mmap
+        } else {
+            let ring = Mmap::new_anon(ring_entries as usize * mem::size_of::<BufRingEntry>())?;
+
+            // SAFETY: the ring memory is moved into the `BufRing`, which unregisters it on drop.
+            unsafe {
+                submitter.register_buf_ring_with_flags(
+                    ring.as_mut_ptr() as _,
+                    ring_entries,
+                    self.bgid,
+                    self.flags,
+                )?;
+            }
+            ring
+        };
+        let buf_ring = BufRing {
+            fd,
+            bgid: self.bgid,
+            mask: ring_entries - 1,
+            buf_len: self.buf_len,
+            ring: ManuallyDrop::new(ring),
+            bufs: ManuallyDrop::new(bufs),
+            tail: Cell::new(0),
+            states: (0..buf_cnt)
+                .map(|_| BufState {
+                    kernel: Cell::new(true),
+                    offset: Cell::new(0),
+                    refs: Cell::new(0),
+                })
+                .collect(),
+        };
+
+        if self.dontfork {
+            buf_ring.ring.dontfork()?;
+            buf_ring.bufs.dontfork()?;
+        }
+
+        for bid in 0..buf_cnt {
+            buf_ring.push(bid);
+        }
+        buf_ring.sync();
+
+        Ok(buf_ring)
+    }
+}
+
+impl Buf<'_> {
+    /// The buffer id.
+    pub fn bid(&self) -> u16 {
+        self.bid
+    }
+
+    /// The offset of the data in the buffer, which is only non-zero for buffers of an
+    /// [incremental](Builder::incremental) ring.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The capacity of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.ring.buf_len
+    }
+}
+
+impl ops::Deref for Buf<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ring.buf_ptr(self.bid).add(self.offset), self.len) }
+    }
+}
+
+impl ops::DerefMut for Buf<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ring.buf_ptr(self.bid).add(self.offset), self.len) }
+    }
+}
+
+impl Drop for Buf<'_> {
+    fn drop(&mut self) {
+        let state = &self.ring.states[self.bid as usize];
+        state.refs.set(state.refs.get() - 1);
+        if state.refs.get() == 0 && !state.kernel.get() {
+            state.kernel.set(true);
+            self.ring.push(self.bid);
+            self.ring.sync();
+        }
+    }
+}
+
+impl fmt::Debug for Buf<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buf")
+            .field("bgid", &self.ring.bgid)
+            .field("bid", &self.bid)
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .finish()
+    }
+}
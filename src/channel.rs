@@ -0,0 +1,218 @@
+//! A channel between io_uring instances.
+//!
+//! A [`RingChannel`] moves messages from one ring to another, for example to hand accepted
+//! connections from an acceptor ring to per-thread worker rings. The message is kept in a slab
+//! shared by the clones of the channel, and only its index is sent with
+//! [`MsgRingData`](crate::opcode::MsgRingData), or with
+//! [`MsgRingSendFd`](crate::opcode::MsgRingSendFd) together with a fixed file.
+
+use std::os::unix::io::RawFd;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::{fmt, io};
+
+use crate::squeue::{self, PushError, SubmissionQueue};
+use crate::{cqueue, opcode, types};
+
+/// A channel moving messages of type `T` between io_uring instances.
+///
+/// The completions of the channel are recognized by their `user_data`, of which the upper 32
+/// bits are the `id` of the channel and the lower 32 bits the index of the message in the slab.
+/// On the target ring, [`recv`](Self::recv) turns the completion back into the message. Sends
+/// only post a completion to the sending ring if they fail, which [`recv`](Self::recv) returns
+/// along with the message.
+///
+/// ```no_run
+/// # use ntex_io_uring::{channel::{Message, RingChannel}, IoUring};
+/// # use std::os::unix::io::AsRawFd;
+/// # fn main() -> std::io::Result<()> {
+/// let acceptor = IoUring::new(8)?;
+/// let mut worker = IoUring::new(8)?;
+/// let channel = RingChannel::new(1);
+///
+/// let sq = acceptor.submission();
+/// channel.send(&sq, worker.as_raw_fd(), "hello").expect("queue is full");
+/// sq.sync();
+/// acceptor.submit()?;
+///
+/// worker.submit_and_wait(1)?;
+/// for cqe in worker.completion() {
+///     if let Some(Message::Received { msg, .. }) = channel.recv(&cqe) {
+///         assert_eq!(msg, "hello");
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct RingChannel<T> {
+    id: u32,
+    slab: Arc<Mutex<Slab<T>>>,
+}
+
+struct Slab<T> {
+    entries: Vec<Option<T>>,
+    free: Vec<u32>,
+}
+
+/// A completion of a [`RingChannel`], see [`RingChannel::recv`].
+#[derive(Debug)]
+pub enum Message<T> {
+    /// A message was received on the target ring.
+    ///
+    /// For a message sent with [`send_fd`](RingChannel::send_fd), `result` is the slot of the
+    /// file when it was allocated by the kernel, and zero otherwise.
+    Received { msg: T, result: i32 },
+    /// Sending the message failed, it is handed back on the sending ring.
+    Failed { msg: T, error: io::Error },
+}
+
+impl<T> RingChannel<T> {
+    /// Create a channel, whose completions are recognized by `id`.
+    pub fn new(id: u32) -> Self {
+        RingChannel {
+            id,
+            slab: Arc::new(Mutex::new(Slab {
+                entries: Vec::new(),
+                free: Vec::new(),
+            })),
+        }
+    }
+
+    /// The id of the channel.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns `true` if `user_data` belongs to a completion of this channel.
+    pub fn owns(&self, user_data: u64) -> bool {
+        (user_data >> 32) as u32 == self.id
+    }
+
+    /// The number of messages in flight.
+    pub fn len(&self) -> usize {
+        let slab = self.lock();
+        slab.entries.len() - slab.free.len()
+    }
+
+    /// Returns `true` if no messages are in flight.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Push an entry to `sq` sending `msg` to the ring of `ring_fd`.
+    ///
+    /// If the queue is full, the message is handed back.
+    pub fn send<E: squeue::EntryMarker>(
+        &self,
+        sq: &SubmissionQueue<'_, E>,
+        ring_fd: RawFd,
+        msg: T,
+    ) -> Result<(), T> {
+        self.push(sq, msg, |user_data| {
+            opcode::MsgRingData::new(types::Fd(ring_fd), 0, user_data, None).build()
+        })
+    }
+
+    /// Push an entry to `sq` sending `msg` along with the fixed file `fixed` to the ring of
+    /// `ring_fd`, where it is installed in `dest`.
+    ///
+    /// If the queue is full, the message is handed back.
+    pub fn send_fd<E: squeue::EntryMarker>(
+        &self,
+        sq: &SubmissionQueue<'_, E>,
+        ring_fd: RawFd,
+        fixed: types::Fixed,
+        dest: types::DestinationSlot,
+        msg: T,
+    ) -> Result<(), T> {
+        self.push(sq, msg, |user_data| {
+            opcode::MsgRingSendFd::new(types::Fd(ring_fd), fixed, dest, user_data).build()
+        })
+    }
+
+    /// Take the message of a completion of this channel.
+    ///
+    /// Returns `None` if the completion belongs to another request.
+    pub fn recv<E: cqueue::EntryMarker>(&self, cqe: &E) -> Option<Message<T>> {
+        let cqe: cqueue::Entry = cqe.clone().into();
+        if !self.owns(cqe.user_data()) {
+            return None;
+        }
+
+        let msg = self.lock().remove(cqe.user_data() as u32)?;
+        let result = cqe.result();
+        Some(if result < 0 {
+            Message::Failed {
+                msg,
+                error: io::Error::from_raw_os_error(-result),
+            }
+        } else {
+            Message::Received { msg, result }
+        })
+    }
+
+    fn push<E, F>(&self, sq: &SubmissionQueue<'_, E>, msg: T, build: F) -> Result<(), T>
+    where
+        E: squeue::EntryMarker,
+        F: FnOnce(u64) -> squeue::Entry,
+    {
+        if sq.is_full() {
+            return Err(msg);
+        }
+
+        let index = self.lock().insert(msg);
+        let user_data = u64::from(self.id) << 32 | u64::from(index);
+        let entry = build(user_data)
+            .user_data(user_data)
+            .flags(squeue::Flags::SKIP_SUCCESS);
+
+        // Safety: the entry only refers to the target ring and fixed file, which are checked
+        // by the kernel.
+        match unsafe { sq.push(&E::from(entry)) } {
+            Ok(()) => Ok(()),
+            Err(PushError) => Err(self.lock().remove(index).unwrap()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Slab<T>> {
+        self.slab.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Slab<T> {
+    fn insert(&mut self, msg: T) -> u32 {
+        match self.free.pop() {
+            Some(index) => {
+                self.entries[index as usize] = Some(msg);
+                index
+            }
+            None => {
+                self.entries.push(Some(msg));
+                (self.entries.len() - 1) as u32
+            }
+        }
+    }
+
+    fn remove(&mut self, index: u32) -> Option<T> {
+        let msg = self.entries.get_mut(index as usize)?.take()?;
+        self.free.push(index);
+        Some(msg)
+    }
+}
+
+impl<T> Clone for RingChannel<T> {
+    fn clone(&self) -> Self {
+        RingChannel {
+            id: self.id,
+            slab: self.slab.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for RingChannel<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RingChannel")
+            .field("id", &self.id)
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -3,10 +3,11 @@
 use std::fmt::{self, Debug};
 use std::mem;
 use std::mem::MaybeUninit;
+use std::ptr;
 use std::sync::atomic;
 
 use crate::sys;
-use crate::util::{private, unsync_load, Mmap};
+use crate::util::{private, unsync_load};
 
 pub(crate) struct Inner<E: EntryMarker> {
     head: *const atomic::AtomicU32,
@@ -53,14 +54,14 @@ fn test_entry_sizes() {
 
 impl<E: EntryMarker> Inner<E> {
     #[rustfmt::skip]
-    pub(crate) unsafe fn new(cq_mmap: &Mmap, p: &sys::io_uring_params) -> Self {
-        let head         = cq_mmap.offset(p.cq_off.head         ) as *const atomic::AtomicU32;
-        let tail         = cq_mmap.offset(p.cq_off.tail         ) as *const atomic::AtomicU32;
-        let ring_mask    = cq_mmap.offset(p.cq_off.ring_mask    ).cast::<u32>().read();
-        let ring_entries = cq_mmap.offset(p.cq_off.ring_entries ).cast::<u32>().read();
-        let overflow     = cq_mmap.offset(p.cq_off.overflow     ) as *const atomic::AtomicU32;
-        let cqes         = cq_mmap.offset(p.cq_off.cqes         ) as *const E;
-        let flags        = cq_mmap.offset(p.cq_off.flags        ) as *const atomic::AtomicU32;
+    pub(crate) unsafe fn new(cq_ring: *mut libc::c_void, p: &sys::io_uring_params) -> Self {
+        let head         = cq_ring.add(p.cq_off.head         as usize) as *const atomic::AtomicU32;
+        let tail         = cq_ring.add(p.cq_off.tail         as usize) as *const atomic::AtomicU32;
+        let ring_mask    = cq_ring.add(p.cq_off.ring_mask    as usize).cast::<u32>().read();
+        let ring_entries = cq_ring.add(p.cq_off.ring_entries as usize).cast::<u32>().read();
+        let overflow     = cq_ring.add(p.cq_off.overflow     as usize) as *const atomic::AtomicU32;
+        let cqes         = cq_ring.add(p.cq_off.cqes         as usize) as *const E;
+        let flags        = cq_ring.add(p.cq_off.flags        as usize) as *const atomic::AtomicU32;
 
         Self {
             head,
@@ -86,6 +87,35 @@ impl<E: EntryMarker> Inner<E> {
     pub(crate) fn borrow(&mut self) -> CompletionQueue<'_, E> {
         unsafe { self.borrow_shared() }
     }
+
+    // Remove the first available entry with `user_data`, moving the entries before it by one.
+    // Returns the number of the other available entries, and the result of the removed entry.
+    //
+    // Safety: no `CompletionQueue` may be alive, nor any other call to `take`, as the head is
+    // moved.
+    pub(crate) unsafe fn take(&self, user_data: u64) -> (u32, Option<i32>) {
+        let head = unsync_load(self.head);
+        let tail = (*self.tail).load(atomic::Ordering::Acquire);
+        let cqes = self.cqes as *mut E;
+        let slot = |pos: u32| cqes.add((pos & self.ring_mask) as usize);
+
+        let mut pos = head;
+        while pos != tail {
+            let entry: Entry = (*slot(pos)).clone().into();
+            if entry.user_data() == user_data {
+                while pos != head {
+                    let prev = pos.wrapping_sub(1);
+                    ptr::copy_nonoverlapping(slot(prev), slot(pos), 1);
+                    pos = prev;
+                }
+                (*self.head).store(head.wrapping_add(1), atomic::Ordering::Release);
+                return (tail.wrapping_sub(head) - 1, Some(entry.result()));
+            }
+            pos = pos.wrapping_add(1);
+        }
+
+        (tail.wrapping_sub(head), None)
+    }
 }
 
 impl<E: EntryMarker> CompletionQueue<'_, E> {
@@ -45,10 +45,18 @@
 
 #[macro_use]
 mod util;
+pub mod buf_ring;
+pub mod channel;
 pub mod cqueue;
+pub mod multishot;
 pub mod opcode;
 pub mod opcode2;
+pub mod pool;
 pub mod register;
+#[cfg(feature = "runtime")]
+pub mod runtime;
+#[cfg(feature = "safe")]
+pub mod safe;
 pub mod squeue;
 mod submit;
 mod sys;
@@ -57,13 +65,15 @@ pub mod types;
 use std::marker::PhantomData;
 use std::mem::ManuallyDrop;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic;
+use std::time::Duration;
 use std::{cmp, io, mem};
 
 #[cfg(feature = "io_safety")]
 use std::os::unix::io::{AsFd, BorrowedFd};
 
 pub use cqueue::CompletionQueue;
-pub use register::Probe;
+pub use register::{send_msg_ring, Probe};
 pub use squeue::SubmissionQueue;
 pub use submit::EnterFlags;
 pub use submit::Submitter;
@@ -84,14 +94,25 @@ where
     cq: cqueue::Inner<C>,
     fd: OwnedFd,
     params: Parameters,
+    ring_index: submit::RingIndex,
+    dontfork: bool,
     memory: ManuallyDrop<MemoryMap>,
+    // Serializes the waits which take their timeout request out of the completion queue.
 }
 
-#[allow(dead_code)]
-struct MemoryMap {
-    sq_mmap: Mmap,
-    sqe_mmap: Mmap,
-    cq_mmap: Option<Mmap>,
+enum MemoryMap {
+    /// Rings allocated by the kernel and mapped into memory with `mmap(2)`.
+    Kernel {
+        sq_mmap: Mmap,
+        sqe_mmap: Mmap,
+        cq_mmap: Option<Mmap>,
+    },
+    /// Rings placed in application memory, see [`Builder::setup_no_mmap`]. A region is owned when
+    /// it was allocated by the [`Builder`], and borrowed from the application otherwise.
+    User {
+        rings: Option<Mmap>,
+        sqes: Option<Mmap>,
+    },
 }
 
 /// IoUring build params
@@ -102,6 +123,7 @@ where
     C: cqueue::EntryMarker,
 {
     dontfork: bool,
+    user_memory_len: Option<(usize, usize)>,
     params: sys::io_uring_params,
     phantom: PhantomData<(S, C)>,
 }
@@ -133,7 +155,8 @@ impl IoUring<squeue::Entry, cqueue::Entry> {
     ///
     /// The caller must uphold that the file descriptor is owned and refers to a uring. The
     /// `params` argument must be equivalent to the those previously filled in by the kernel when
-    /// the provided ring was created.
+    /// the provided ring was created. If the ring was created with `IORING_SETUP_NO_MMAP`, the
+    /// memory its rings live in must stay valid until the returned instance is dropped.
     pub unsafe fn from_fd(fd: RawFd, params: Parameters) -> io::Result<Self> {
         Self::with_fd_and_params(OwnedFd::from_raw_fd(fd), params.0)
     }
@@ -150,6 +173,7 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> IoUring<S, C> {
     pub fn builder() -> Builder<S, C> {
         Builder {
             dontfork: false,
+            user_memory_len: None,
             params: sys::io_uring_params {
                 flags: S::BUILD_FLAGS | C::BUILD_FLAGS,
                 ..Default::default()
@@ -164,52 +188,6 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> IoUring<S, C> {
     }
 
     unsafe fn with_fd_and_params(fd: OwnedFd, p: sys::io_uring_params) -> io::Result<Self> {
-        // NOTE: The `SubmissionQueue` and `CompletionQueue` are references,
-        // and their lifetime can never exceed `MemoryMap`.
-        //
-        // The memory mapped regions of `MemoryMap` never move,
-        // so `SubmissionQueue` and `CompletionQueue` are `Unpin`.
-        //
-        // I really hope that Rust can safely use self-reference types.
-        #[inline]
-        unsafe fn setup_queue<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
-            fd: &OwnedFd,
-            p: &sys::io_uring_params,
-        ) -> io::Result<(MemoryMap, squeue::Inner<S>, cqueue::Inner<C>)> {
-            let sq_len = p.sq_off.array as usize + p.sq_entries as usize * mem::size_of::<u32>();
-            let cq_len = p.cq_off.cqes as usize + p.cq_entries as usize * mem::size_of::<C>();
-            let sqe_len = p.sq_entries as usize * mem::size_of::<S>();
-            let sqe_mmap = Mmap::new(fd, sys::IORING_OFF_SQES as _, sqe_len)?;
-
-            if p.features & sys::IORING_FEAT_SINGLE_MMAP != 0 {
-                let scq_mmap =
-                    Mmap::new(fd, sys::IORING_OFF_SQ_RING as _, cmp::max(sq_len, cq_len))?;
-
-                let sq = squeue::Inner::new(&scq_mmap, &sqe_mmap, p);
-                let cq = cqueue::Inner::new(&scq_mmap, p);
-                let mm = MemoryMap {
-                    sq_mmap: scq_mmap,
-                    cq_mmap: None,
-                    sqe_mmap,
-                };
-
-                Ok((mm, sq, cq))
-            } else {
-                let sq_mmap = Mmap::new(fd, sys::IORING_OFF_SQ_RING as _, sq_len)?;
-                let cq_mmap = Mmap::new(fd, sys::IORING_OFF_CQ_RING as _, cq_len)?;
-
-                let sq = squeue::Inner::new(&sq_mmap, &sqe_mmap, p);
-                let cq = cqueue::Inner::new(&cq_mmap, p);
-                let mm = MemoryMap {
-                    cq_mmap: Some(cq_mmap),
-                    sq_mmap,
-                    sqe_mmap,
-                };
-
-                Ok((mm, sq, cq))
-            }
-        }
-
         let (mm, sq, cq) = unsafe { setup_queue(&fd, &p)? };
 
         Ok(IoUring {
@@ -217,6 +195,8 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> IoUring<S, C> {
             cq,
             fd,
             params: Parameters(p),
+            ring_index: submit::RingIndex::new(),
+            dontfork: false,
             memory: ManuallyDrop::new(mm),
         })
     }
@@ -228,6 +208,7 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> IoUring<S, C> {
         Submitter::new(
             &self.fd,
             &self.params,
+            &self.ring_index,
             self.sq.head,
             self.sq.tail,
             self.sq.flags,
@@ -253,6 +234,206 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> IoUring<S, C> {
         self.submitter().submit_and_wait(want)
     }
 
+    /// Submit the queued entries and wait for at least `want` completions, or until `timeout`
+    /// expired.
+    ///
+    /// On kernels without [`is_feature_ext_arg`](Parameters::is_feature_ext_arg), the timeout is
+    /// a [`Timeout`](opcode::Timeout) request with the `user_data`
+    /// [`WAIT_TIMEOUT_USER_DATA`](types::WAIT_TIMEOUT_USER_DATA), whose completions are taken
+    /// out of the completion queue before returning.
+    pub fn wait(
+        &mut self,
+        want: usize,
+        timeout: Option<Duration>,
+    ) -> io::Result<types::WaitStatus> {
+        self.wait_with(want, None, timeout)
+    }
+
+    /// Like [`wait`](Self::wait), but only waits for `want` completions for `min_wait`, after
+    /// which a single completion is enough.
+    ///
+    /// `min_wait` is ignored on kernels without
+    /// [`is_feature_min_timeout`](Parameters::is_feature_min_timeout), see
+    /// [`SubmitArgs::min_wait_usec`](types::SubmitArgs::min_wait_usec).
+    pub fn wait_min(
+        &mut self,
+        want: usize,
+        min_wait: Duration,
+        timeout: Option<Duration>,
+    ) -> io::Result<types::WaitStatus> {
+        let min_wait = Some(min_wait).filter(|_| self.params.is_feature_min_timeout());
+        self.wait_with(want, min_wait, timeout)
+    }
+
+    fn wait_with(
+        &mut self,
+        want: usize,
+        min_wait: Option<Duration>,
+        timeout: Option<Duration>,
+    ) -> io::Result<types::WaitStatus> {
+        // Waiting for no completions never times out.
+        let timeout = timeout.filter(|_| want > 0);
+        self.submit()?;
+
+        let res = match (timeout, min_wait) {
+            (None, None) => self.wait_submitted(want, None),
+            // `min_wait` is only set with `is_feature_min_timeout`, which implies `EXT_ARG`.
+            (Some(timeout), _) if !self.params.is_feature_ext_arg() => {
+                return self.wait_with_timeout_op(want, timeout);
+            }
+            (timeout, min_wait) => {
+                let ts = timeout.map(types::Timespec::from);
+                let args = match &ts {
+                    Some(ts) => types::SubmitArgs::new().timespec(ts),
+                    None => types::SubmitArgs::new(),
+                };
+                let args = match min_wait {
+                    Some(min_wait) => {
+                        let usec = cmp::min(min_wait.as_micros(), u32::MAX.into());
+                        args.min_wait_usec(usec as u32)
+                    }
+                    None => args,
+                };
+                self.wait_submitted(want, Some(&args))
+            }
+        };
+
+        match res {
+            Ok(_) => Ok(types::WaitStatus::Ready),
+            Err(err) if err.raw_os_error() == Some(libc::ETIME) => Ok(types::WaitStatus::TimedOut),
+            Err(err) if err.raw_os_error() == Some(libc::EINTR) => {
+                Ok(types::WaitStatus::Interrupted)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // Wait for completions once the queued entries are submitted, as the kernel returns the
+    // number of submitted entries rather than the outcome of the wait when it submits some.
+    fn wait_submitted(
+        &self,
+        want: usize,
+        args: Option<&types::SubmitArgs<'_, '_>>,
+    ) -> io::Result<usize> {
+        let submitter = self.submitter();
+        let flags = EnterFlags::GETEVENTS;
+        unsafe {
+            match args {
+                Some(args) if args.absolute => submitter.enter(
+                    0,
+                    want as u32,
+                    (flags | EnterFlags::EXT_ARG | EnterFlags::ABS_TIMER).bits(),
+                    Some(&args.args),
+                ),
+                Some(args) => submitter.enter(
+                    0,
+                    want as u32,
+                    (flags | EnterFlags::EXT_ARG).bits(),
+                    Some(&args.args),
+                ),
+                None => submitter.enter::<libc::sigset_t>(0, want as u32, flags.bits(), None),
+            }
+        }
+    }
+
+    // Wait with a timeout request counting `want` completions, on kernels before 5.11.
+    fn wait_with_timeout_op(
+        &mut self,
+        want: usize,
+        timeout: Duration,
+    ) -> io::Result<types::WaitStatus> {
+        const USER_DATA: u64 = types::WAIT_TIMEOUT_USER_DATA;
+
+        // The kernel reads the timespec until the timeout completes, including after an early
+        // return.
+        let ts = Box::new(types::Timespec::from(timeout));
+        let entry = opcode::Timeout::new(&*ts)
+            .count(want as u32)
+            .build()
+            .user_data(USER_DATA);
+        unsafe { self.push_or_submit(&S::from(entry))? };
+
+        let status = match self.submit() {
+            Ok(_) => loop {
+                // Safety: the ring is borrowed mutably, so no `CompletionQueue` is alive.
+                let (len, result) = unsafe { self.cq.take(USER_DATA) };
+                match result {
+                    Some(res) if res == -libc::ETIME => return Ok(types::WaitStatus::TimedOut),
+                    Some(res) if res >= 0 => return Ok(types::WaitStatus::Ready),
+                    Some(res) => return Err(io::Error::from_raw_os_error(-res)),
+                    None => (),
+                }
+
+                match self.wait_submitted(len as usize + 1, None) {
+                    Ok(_) => (),
+                    Err(err) if err.raw_os_error() == Some(libc::EINTR) => {
+                        break Ok(types::WaitStatus::Interrupted)
+                    }
+                    Err(err) => break Err(err),
+                }
+            },
+            Err(err) => Err(err),
+        };
+
+        // Remove the timeout, and take its completion and the one of the removal.
+        let entry = opcode::TimeoutRemove::new(USER_DATA)
+            .build()
+            .user_data(USER_DATA);
+        let mut pending = 2;
+        let reaped = unsafe { self.push_or_submit(&S::from(entry)) }.and_then(|()| {
+            self.submit()?;
+            while pending > 0 {
+                match unsafe { self.cq.take(USER_DATA) } {
+                    (_, Some(_)) => pending -= 1,
+                    (len, None) => match self.wait_submitted(len as usize + 1, None) {
+                        Ok(_) => (),
+                        Err(err) if err.raw_os_error() == Some(libc::EINTR) => (),
+                        Err(err) => return Err(err),
+                    },
+                }
+            }
+            Ok(())
+        });
+        if reaped.is_err() {
+            // The timeout may still be armed.
+            mem::forget(ts);
+        }
+
+        status
+    }
+
+    /// Push an entry to the submission queue, submitting the queued entries to make room if the
+    /// queue is full.
+    ///
+    /// With [`setup_sqpoll`](Builder::setup_sqpoll), the kernel thread is woken up if needed,
+    /// and this waits with [`Submitter::squeue_wait`] for it to consume entries. The pushed
+    /// entry is synchronized with the kernel, but only submitted once the queue is full or on
+    /// the next [`submit`](Self::submit).
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of the entry (such as buffer) are valid and will
+    /// be valid for the entire duration of the operation, otherwise it may cause memory problems.
+    pub unsafe fn push_or_submit(&self, entry: &S) -> io::Result<()> {
+        let sq = self.submission();
+        loop {
+            sq.sync();
+            if sq.push(entry).is_ok() {
+                sq.sync();
+                return Ok(());
+            }
+
+            let submitter = self.submitter();
+            submitter.submit()?;
+            if self.params.is_setup_sqpoll() {
+                sq.sync();
+                if sq.is_full() {
+                    submitter.squeue_wait()?;
+                }
+            }
+        }
+    }
+
     /// Get the submitter, submission queue and completion queue of the io_uring instance. This can
     /// be used to operate on the different parts of the io_uring instance independently.
     ///
@@ -270,6 +451,7 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> IoUring<S, C> {
         let submit = Submitter::new(
             &self.fd,
             &self.params,
+            &self.ring_index,
             self.sq.head,
             self.sq.tail,
             self.sq.flags,
@@ -277,6 +459,129 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> IoUring<S, C> {
         (submit, self.sq.borrow(), self.cq.borrow())
     }
 
+    /// Resize the submission queue to `sq_entries` and the completion queue to `cq_entries`
+    /// entries, which must be at least `sq_entries`. Both may be rounded up to the next
+    /// power-of-two.
+    ///
+    /// Entries pending in either queue are moved to the new rings, including the ones pushed but
+    /// not yet [synchronized](SubmissionQueue::sync). Returns `EOVERFLOW` if they do not fit.
+    ///
+    /// The ring must have been set up with [`Builder::setup_defer_taskrun`], and the resize must
+    /// happen on the submitter task. Rings placed in memory provided with
+    /// [`Builder::setup_no_mmap_with_memory`] cannot be resized and fail with `InvalidInput`.
+    ///
+    /// The kernel does not report where the indirection array of the new rings is, so only rings
+    /// set up without it, see [`Parameters::is_setup_no_sqarray`], can be resized. Others fail
+    /// with `Unsupported`.
+    ///
+    /// If the new rings cannot be mapped after the kernel switched to them, the error is
+    /// returned and both queues are left empty with no capacity, so that no entry can be
+    /// pushed and no completion is seen until the rings are successfully resized again.
+    /// Available since 6.13.
+    pub fn resize(&mut self, sq_entries: u32, cq_entries: u32) -> io::Result<()> {
+        if self.params.0.flags & sys::IORING_SETUP_NO_SQARRAY == 0 {
+            return Err(io::Error::from(io::ErrorKind::Unsupported));
+        }
+
+        let mut p = sys::io_uring_params {
+            sq_entries,
+            cq_entries,
+            flags: sys::IORING_SETUP_CQSIZE,
+            ..Default::default()
+        };
+
+        // The kernel only checks that the synchronized entries fit, the others are moved by us.
+        let head = unsafe { (*self.sq.head).load(atomic::Ordering::Acquire) };
+        let pending = self.sq.local_tail.get().wrapping_sub(head);
+        if pending > sq_entries.checked_next_power_of_two().unwrap_or(u32::MAX) {
+            return Err(io::Error::from_raw_os_error(libc::EOVERFLOW));
+        }
+
+        let memory = match &*self.memory {
+            MemoryMap::Kernel { .. } => None,
+            MemoryMap::User {
+                rings: Some(_),
+                sqes: Some(_),
+            } => {
+                let (rings_len, sqes_len) = Self::builder()
+                    .setup_cqsize(cq_entries)
+                    .no_mmap_sizes(sq_entries);
+                let rings = Mmap::new_anon(rings_len)?;
+                let sqes = Mmap::new_anon(sqes_len)?;
+                p.cq_off.user_addr = rings.as_mut_ptr() as _;
+                p.sq_off.user_addr = sqes.as_mut_ptr() as _;
+                Some(MemoryMap::User {
+                    rings: Some(rings),
+                    sqes: Some(sqes),
+                })
+            }
+            MemoryMap::User { .. } => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        };
+
+        register::execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_RESIZE_RINGS,
+            (&mut p as *mut sys::io_uring_params).cast(),
+            1,
+        )?;
+
+        let mut params = self.params.0;
+        params.sq_entries = p.sq_entries;
+        params.cq_entries = p.cq_entries;
+        params.sq_off = p.sq_off;
+        params.cq_off = p.cq_off;
+
+        let (mut mm, sq, cq) = match unsafe { setup_queue(&self.fd, &params) } {
+            Ok(queues) => queues,
+            Err(err) => {
+                self.poison(memory);
+                return Err(err);
+            }
+        };
+        if let Some(memory) = memory {
+            mm = memory;
+        }
+
+        // The old rings stay mapped until the pending entries are moved.
+        unsafe {
+            sq.copy_unsynced_from(&self.sq);
+        }
+        self.sq = sq;
+        self.cq = cq;
+        self.params = Parameters(params);
+        unsafe {
+            ManuallyDrop::drop(&mut self.memory);
+        }
+        self.memory = ManuallyDrop::new(mm);
+
+        if self.dontfork {
+            self.memory.dontfork()?;
+        }
+
+        Ok(())
+    }
+
+    // Leave queues with no capacity, after the kernel switched to rings which cannot be used. The
+    // memory of the new rings, if allocated by us, is kept since the kernel uses it.
+    fn poison(&mut self, memory: Option<MemoryMap>) {
+        static EMPTY: atomic::AtomicU32 = atomic::AtomicU32::new(0);
+
+        // Every field of the rings is the same zero, and there are no entries.
+        let p = sys::io_uring_params {
+            flags: sys::IORING_SETUP_NO_SQARRAY,
+            ..Default::default()
+        };
+        let ring = &EMPTY as *const atomic::AtomicU32 as *mut libc::c_void;
+        unsafe {
+            self.sq = squeue::Inner::new(ring, ring, &p);
+            self.cq = cqueue::Inner::new(ring, &p);
+            if let Some(memory) = memory {
+                ManuallyDrop::drop(&mut self.memory);
+                self.memory = ManuallyDrop::new(memory);
+            }
+        }
+    }
+
     /// Get the submission queue of the io_uring instance. This is used to send I/O requests to the
     /// kernel.
     #[inline]
@@ -315,6 +620,14 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> IoUring<S, C> {
 
 impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Drop for IoUring<S, C> {
     fn drop(&mut self) {
+        // A registered ring fd holds a reference to the ring, so it must be released for the
+        // ring to be destroyed before the registering thread exits. This is only possible on
+        // the registering thread.
+        let submitter = self.submitter();
+        if submitter.registered_ring_fd().is_some() {
+            let _ = submitter.unregister_ring_fd();
+        }
+
         // Ensure that `MemoryMap` is released before `fd`.
         unsafe {
             ManuallyDrop::drop(&mut self.memory);
@@ -322,6 +635,97 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Drop for IoUring<S, C> {
     }
 }
 
+impl MemoryMap {
+    /// Do not make the rings accessible by child processes after a fork. Memory borrowed from the
+    /// application is left untouched.
+    fn dontfork(&self) -> io::Result<()> {
+        match self {
+            MemoryMap::Kernel {
+                sq_mmap,
+                sqe_mmap,
+                cq_mmap,
+            } => {
+                sq_mmap.dontfork()?;
+                sqe_mmap.dontfork()?;
+                if let Some(cq_mmap) = cq_mmap {
+                    cq_mmap.dontfork()?;
+                }
+            }
+            MemoryMap::User { rings, sqes } => {
+                for mmap in rings.iter().chain(sqes.iter()) {
+                    mmap.dontfork()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// NOTE: The `SubmissionQueue` and `CompletionQueue` are references,
+// and their lifetime can never exceed `MemoryMap`.
+//
+// The memory mapped regions of `MemoryMap` never move,
+// so `SubmissionQueue` and `CompletionQueue` are `Unpin`.
+//
+// I really hope that Rust can safely use self-reference types.
+#[inline]
+unsafe fn setup_queue<S: squeue::EntryMarker, C: cqueue::EntryMarker>(
+    fd: &OwnedFd,
+    p: &sys::io_uring_params,
+) -> io::Result<(MemoryMap, squeue::Inner<S>, cqueue::Inner<C>)> {
+    if p.flags & sys::IORING_SETUP_NO_MMAP != 0 {
+        // Both rings share a single region, which is owned by the application.
+        let rings = p.cq_off.user_addr as *mut libc::c_void;
+        let sqes = p.sq_off.user_addr as *mut libc::c_void;
+
+        let sq = squeue::Inner::new(rings, sqes, p);
+        let cq = cqueue::Inner::new(rings, p);
+        let mm = MemoryMap::User {
+            rings: None,
+            sqes: None,
+        };
+
+        return Ok((mm, sq, cq));
+    }
+
+    let sq_len = if p.flags & sys::IORING_SETUP_NO_SQARRAY != 0 {
+        // Without the indirection array, the SQ ring ends before the CQEs.
+        p.cq_off.cqes as usize
+    } else {
+        p.sq_off.array as usize + p.sq_entries as usize * mem::size_of::<u32>()
+    };
+    let cq_len = p.cq_off.cqes as usize + p.cq_entries as usize * mem::size_of::<C>();
+    let sqe_len = p.sq_entries as usize * mem::size_of::<S>();
+    let sqe_mmap = Mmap::new(fd, sys::IORING_OFF_SQES as _, sqe_len)?;
+
+    if p.features & sys::IORING_FEAT_SINGLE_MMAP != 0 {
+        let scq_mmap = Mmap::new(fd, sys::IORING_OFF_SQ_RING as _, cmp::max(sq_len, cq_len))?;
+
+        let sq = squeue::Inner::new(scq_mmap.as_mut_ptr(), sqe_mmap.as_mut_ptr(), p);
+        let cq = cqueue::Inner::new(scq_mmap.as_mut_ptr(), p);
+        let mm = MemoryMap::Kernel {
+            sq_mmap: scq_mmap,
+            cq_mmap: None,
+            sqe_mmap,
+        };
+
+        Ok((mm, sq, cq))
+    } else {
+        let sq_mmap = Mmap::new(fd, sys::IORING_OFF_SQ_RING as _, sq_len)?;
+        let cq_mmap = Mmap::new(fd, sys::IORING_OFF_CQ_RING as _, cq_len)?;
+
+        let sq = squeue::Inner::new(sq_mmap.as_mut_ptr(), sqe_mmap.as_mut_ptr(), p);
+        let cq = cqueue::Inner::new(cq_mmap.as_mut_ptr(), p);
+        let mm = MemoryMap::Kernel {
+            cq_mmap: Some(cq_mmap),
+            sq_mmap,
+            sqe_mmap,
+        };
+
+        Ok((mm, sq, cq))
+    }
+}
+
 impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Builder<S, C> {
     /// Do not make this io_uring instance accessible by child processes after a fork.
     pub fn dontfork(&mut self) -> &mut Self {
@@ -465,17 +869,137 @@ impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Builder<S, C> {
         self
     }
 
+    /// Let the kernel index the submission queue entries directly by the ring head, instead of
+    /// going through the indirection array that maps ring slots to entries. This saves a memory
+    /// access per submitted entry in the kernel.
+    ///
+    /// Kernels that do not support this flag fail with `EINVAL`, in which case
+    /// [`build`](Self::build) retries without it. Use [`Parameters::is_setup_no_sqarray`] to
+    /// check whether it was applied. Available since 6.6.
+    pub fn setup_no_sqarray(&mut self) -> &mut Self {
+        self.params.flags |= sys::IORING_SETUP_NO_SQARRAY;
+        self
+    }
+
+    /// Place the submission and completion queue rings and the submission queue entries in memory
+    /// allocated by the application instead of by the kernel, so that they do not have to be
+    /// mapped with `mmap(2)`. With this method the memory is allocated by
+    /// [`build`](Self::build) and owned by the [`IoUring`]; use
+    /// [`setup_no_mmap_with_memory`](Self::setup_no_mmap_with_memory) to provide it yourself.
+    ///
+    /// Kernels before 6.10 require each region larger than a page to be backed by a single huge
+    /// page, and fail with `EINVAL` otherwise. Available since 6.5.
+    pub fn setup_no_mmap(&mut self) -> &mut Self {
+        self.params.flags |= sys::IORING_SETUP_NO_MMAP;
+        self.params.cq_off.user_addr = 0;
+        self.params.sq_off.user_addr = 0;
+        self.user_memory_len = None;
+        self
+    }
+
+    /// Like [`setup_no_mmap`](Self::setup_no_mmap), but place the submission and completion queue
+    /// rings in `rings` and the submission queue entries in `sqes`, for example to put several
+    /// rings into a single huge page. [`build`](Self::build) fails with `InvalidInput` if either
+    /// region is smaller than reported by [`no_mmap_sizes`](Self::no_mmap_sizes).
+    ///
+    /// # Safety
+    ///
+    /// Both regions must be page aligned, zero-filled, valid for reads and writes of `rings_len`
+    /// and `sqes_len` bytes respectively, and must not be used for anything else until the built
+    /// [`IoUring`] is dropped. In particular, they must not be shared with another ring built
+    /// from a clone of this builder.
+    pub unsafe fn setup_no_mmap_with_memory(
+        &mut self,
+        rings: *mut libc::c_void,
+        rings_len: usize,
+        sqes: *mut libc::c_void,
+        sqes_len: usize,
+    ) -> &mut Self {
+        self.params.flags |= sys::IORING_SETUP_NO_MMAP;
+        self.params.cq_off.user_addr = rings as _;
+        self.params.sq_off.user_addr = sqes as _;
+        self.user_memory_len = Some((rings_len, sqes_len));
+        self
+    }
+
+    /// The number of bytes needed for the rings and for the submission queue entries of an
+    /// io_uring instance with `entries` entries set up with
+    /// [`setup_no_mmap`](Self::setup_no_mmap), both rounded up to the page size.
+    pub fn no_mmap_sizes(&self, entries: u32) -> (usize, usize) {
+        // Kernel limits on the number of entries, see `IORING_MAX_ENTRIES`.
+        const MAX_SQ_ENTRIES: u32 = 32768;
+        const MAX_CQ_ENTRIES: u32 = 2 * MAX_SQ_ENTRIES;
+        // Upper bound of the size of `struct io_rings` that precedes the CQEs: the four ring
+        // indices sit on their own cache lines, followed by masks, sizes and flags.
+        const RINGS_HEADER_LEN: usize = 1024;
+
+        let sq_entries = cmp::min(entries, MAX_SQ_ENTRIES).next_power_of_two() as usize;
+        let cq_entries = if self.params.flags & sys::IORING_SETUP_CQSIZE != 0 {
+            cmp::min(self.params.cq_entries, MAX_CQ_ENTRIES).next_power_of_two() as usize
+        } else {
+            2 * sq_entries
+        };
+
+        // The indirection array is accounted for even with `setup_no_sqarray`, since `build`
+        // falls back to it on kernels without support for the flag.
+        let rings_len = RINGS_HEADER_LEN
+            + cq_entries * mem::size_of::<C>()
+            + sq_entries * mem::size_of::<u32>();
+        let sqes_len = sq_entries * mem::size_of::<S>();
+
+        let page_size = util::page_size();
+        let round = |len: usize| (len + page_size - 1) & !(page_size - 1);
+        (round(rings_len), round(sqes_len))
+    }
+
     /// Build an [IoUring], with the specified number of entries in the submission queue and
     /// completion queue unless [`setup_cqsize`](Self::setup_cqsize) has been called.
     pub fn build(&self, entries: u32) -> io::Result<IoUring<S, C>> {
-        let ring = IoUring::with_params(entries, self.params)?;
+        let mut params = self.params;
+        let mut memory = None;
+
+        if params.flags & sys::IORING_SETUP_NO_MMAP != 0 {
+            let (rings_len, sqes_len) = self.no_mmap_sizes(entries);
+
+            match self.user_memory_len {
+                Some((user_rings_len, user_sqes_len)) => {
+                    if user_rings_len < rings_len || user_sqes_len < sqes_len {
+                        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+                    }
+                }
+                None => {
+                    let rings = Mmap::new_anon(rings_len)?;
+                    let sqes = Mmap::new_anon(sqes_len)?;
+                    params.cq_off.user_addr = rings.as_mut_ptr() as _;
+                    params.sq_off.user_addr = sqes.as_mut_ptr() as _;
+                    memory = Some(MemoryMap::User {
+                        rings: Some(rings),
+                        sqes: Some(sqes),
+                    });
+                }
+            }
+        }
 
-        if self.dontfork {
-            ring.memory.sq_mmap.dontfork()?;
-            ring.memory.sqe_mmap.dontfork()?;
-            if let Some(cq_mmap) = ring.memory.cq_mmap.as_ref() {
-                cq_mmap.dontfork()?;
+        let mut ring = match IoUring::with_params(entries, params) {
+            Err(e)
+                if e.raw_os_error() == Some(libc::EINVAL)
+                    && params.flags & sys::IORING_SETUP_NO_SQARRAY != 0 =>
+            {
+                // Kernels before 6.6 do not know the flag, fall back to the indirection array.
+                params.flags &= !sys::IORING_SETUP_NO_SQARRAY;
+                IoUring::with_params(entries, params)?
             }
+            res => res?,
+        };
+
+        // The rings were set up in memory allocated above, hand its ownership to the ring.
+        if let Some(memory) = memory {
+            *ring.memory = memory;
+        }
+
+        if self.dontfork {
+            ring.dontfork = true;
+            ring.memory.dontfork()?;
         }
 
         Ok(ring)
@@ -499,6 +1023,18 @@ impl Parameters {
         self.0.flags & sys::IORING_SETUP_SINGLE_ISSUER != 0
     }
 
+    /// Whether the submission queue entries are indexed without the indirection array. Enabled
+    /// with [`Builder::setup_no_sqarray`].
+    pub fn is_setup_no_sqarray(&self) -> bool {
+        self.0.flags & sys::IORING_SETUP_NO_SQARRAY != 0
+    }
+
+    /// Whether the rings live in application memory instead of being mapped from the kernel.
+    /// Enabled with [`Builder::setup_no_mmap`].
+    pub fn is_setup_no_mmap(&self) -> bool {
+        self.0.flags & sys::IORING_SETUP_NO_MMAP != 0
+    }
+
     /// If this flag is set, the SQ and CQ rings were mapped with a single `mmap(2)` call. This
     /// means that only two syscalls were used instead of three.
     pub fn is_feature_single_mmap(&self) -> bool {
@@ -0,0 +1,191 @@
+//! Tracking of multishot requests.
+//!
+//! Multishot requests, such as [`RecvMulti`](crate::opcode::RecvMulti),
+//! [`AcceptMulti`](crate::opcode::AcceptMulti) or a multishot
+//! [`PollAdd`](crate::opcode::PollAdd), post completions flagged with [`cqueue::more`] until
+//! the last one. The kernel may also end them early for a recoverable reason, such as running
+//! out of provided buffers or an overflowing completion queue, in which case they have to be
+//! submitted again. A [`Multishot`] keeps the original entry around to do so.
+
+use crate::cqueue;
+use crate::squeue::{self, PushError, SubmissionQueue};
+
+/// A multishot request, which is re-armed when the kernel ends it for a recoverable reason.
+///
+/// [`update_and_rearm`](Self::update_and_rearm) pushes the entry again as soon as such a
+/// completion is seen. [`update`](Self::update) only records it, leaving the request to be
+/// [armed](Self::arm) again by the caller.
+///
+/// The request is considered to be ended for a recoverable reason when its last completion
+/// has one of the [`rearm_on`](Self::rearm_on) errors (by default only `-ENOBUFS`), or a
+/// positive result, which is what the kernel posts when the completion queue overflows. A
+/// result of zero, such as the end of a stream for receives, ends the request.
+///
+/// ```no_run
+/// # use ntex_io_uring::{multishot::{Multishot, Status}, opcode, types, IoUring};
+/// # fn main() -> std::io::Result<()> {
+/// # let mut ring = IoUring::new(8)?;
+/// # let fd = 0;
+/// let mut recv = Multishot::new(opcode::RecvMulti::new(types::Fd(fd), 1).build().user_data(7));
+/// let (submitter, sq, mut cq) = ring.split();
+///
+/// unsafe { recv.arm(&sq).expect("queue is full") };
+/// loop {
+///     sq.sync();
+///     submitter.submit_and_wait(1)?;
+///     cq.sync();
+///     for cqe in &mut cq {
+///         match unsafe { recv.update_and_rearm(&cqe, &sq) } {
+///             Some(Status::More | Status::Rearm) => { /* handle the completion */ }
+///             Some(Status::Done) => return Ok(()),
+///             None => { /* a completion of another request */ }
+///         }
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Multishot {
+    entry: squeue::Entry,
+    rearm_on: Vec<i32>,
+    state: State,
+    rearms: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Armed,
+    Rearm,
+    Done,
+}
+
+/// What to do after a completion of a [`Multishot`], see [`Multishot::update`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The request is still armed, more completions will follow.
+    More,
+    /// The request was ended for a recoverable reason. It was armed again by
+    /// [`Multishot::update_and_rearm`], or should be [armed](Multishot::arm) again after
+    /// [`Multishot::update`]. A positive result still has to be handled.
+    Rearm,
+    /// The request was ended, no more completions will follow.
+    Done,
+}
+
+impl Multishot {
+    /// Track the multishot request of `entry`, whose completions are recognized by its
+    /// `user_data`.
+    pub fn new(entry: squeue::Entry) -> Self {
+        Multishot {
+            entry,
+            rearm_on: vec![-libc::ENOBUFS],
+            state: State::Idle,
+            rearms: 0,
+        }
+    }
+
+    /// Also re-arm the request when it ends with the error `errno`.
+    pub fn rearm_on(mut self, errno: i32) -> Self {
+        self.rearm_on.push(-errno);
+        self
+    }
+
+    /// The entry of the request.
+    pub fn entry(&self) -> &squeue::Entry {
+        &self.entry
+    }
+
+    /// The `user_data` of the request.
+    pub fn user_data(&self) -> u64 {
+        self.entry.get_user_data()
+    }
+
+    /// Returns `true` if the request is in flight.
+    pub fn is_armed(&self) -> bool {
+        self.state == State::Armed
+    }
+
+    /// Returns `true` if the request was ended for a recoverable reason and is waiting to be
+    /// armed again.
+    pub fn needs_rearm(&self) -> bool {
+        self.state == State::Rearm
+    }
+
+    /// Returns `true` if the request was ended for good.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// How many times the request was armed again.
+    pub fn rearms(&self) -> u32 {
+        self.rearms
+    }
+
+    /// Push the entry of the request to the submission queue, unless it is already armed or
+    /// done.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of the entry (such as buffer) are valid and will
+    /// be valid for the entire duration of the request, including after re-arming.
+    pub unsafe fn arm<E: squeue::EntryMarker>(
+        &mut self,
+        sq: &SubmissionQueue<'_, E>,
+    ) -> Result<(), PushError> {
+        match self.state {
+            State::Idle => (),
+            State::Rearm => self.rearms += 1,
+            State::Armed | State::Done => return Ok(()),
+        }
+        sq.push(&E::from(self.entry.clone()))?;
+        self.state = State::Armed;
+        Ok(())
+    }
+
+    /// Update the state of the request with a completion.
+    ///
+    /// Returns `None` if the completion belongs to another request.
+    pub fn update<E: cqueue::EntryMarker>(&mut self, cqe: &E) -> Option<Status> {
+        let cqe: cqueue::Entry = cqe.clone().into();
+        if cqe.user_data() != self.user_data() {
+            return None;
+        }
+
+        let status = if cqueue::more(cqe.flags()) {
+            Status::More
+        } else if cqe.result() > 0 || self.rearm_on.contains(&cqe.result()) {
+            Status::Rearm
+        } else {
+            Status::Done
+        };
+        self.state = match status {
+            Status::More => State::Armed,
+            Status::Rearm => State::Rearm,
+            Status::Done => State::Done,
+        };
+
+        Some(status)
+    }
+
+    /// Update the state of the request with a completion like [`update`](Self::update), and
+    /// push the entry again to `sq` if the request was ended for a recoverable reason.
+    ///
+    /// If `sq` is full, the request is left [waiting to be armed](Self::needs_rearm) and has
+    /// to be [armed](Self::arm) later.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`arm`](Self::arm).
+    pub unsafe fn update_and_rearm<C: cqueue::EntryMarker, E: squeue::EntryMarker>(
+        &mut self,
+        cqe: &C,
+        sq: &SubmissionQueue<'_, E>,
+    ) -> Option<Status> {
+        let status = self.update(cqe)?;
+        if status == Status::Rearm {
+            let _ = self.arm(sq);
+        }
+        Some(status)
+    }
+}
@@ -1683,7 +1683,8 @@ opcode! {
         user_data: { u64 },
         user_flags: { Option<u32> },
         ;;
-        opcode_flags: u32 = 0
+        /// Options for the message, see [types::MsgRingFlags].
+        opcode_flags: types::MsgRingFlags = types::MsgRingFlags::empty()
     }
 
     pub const CODE = sys::IORING_OP_MSG_RING;
@@ -1697,7 +1698,7 @@ opcode! {
         sqe.fd = ring_fd;
         sqe.len = result as u32;
         sqe.__bindgen_anon_1.off = user_data;
-        sqe.__bindgen_anon_3.msg_ring_flags = opcode_flags;
+        sqe.__bindgen_anon_3.msg_ring_flags = opcode_flags.bits();
         if let Some(flags) = user_flags {
             sqe.__bindgen_anon_5.file_index = flags;
             unsafe {sqe.__bindgen_anon_3.msg_ring_flags |= sys::IORING_MSG_RING_FLAGS_PASS};
@@ -1879,7 +1880,9 @@ opcode! {
         dest_slot_index: { types::DestinationSlot },
         user_data: { u64 },
         ;;
-        opcode_flags: u32 = 0
+        /// Options for the message, such as [types::MsgRingFlags::CQE_SKIP] to not post a
+        /// completion to the target ring.
+        opcode_flags: types::MsgRingFlags = types::MsgRingFlags::empty()
     }
 
     pub const CODE = sys::IORING_OP_MSG_RING;
@@ -1894,7 +1897,7 @@ opcode! {
         sqe.__bindgen_anon_1.off = user_data;
         unsafe { sqe.__bindgen_anon_6.__bindgen_anon_1.as_mut().addr3 = fixed_slot_src.0 as u64 };
         sqe.__bindgen_anon_5.file_index = dest_slot_index.kernel_index_arg();
-        sqe.__bindgen_anon_3.msg_ring_flags = opcode_flags;
+        sqe.__bindgen_anon_3.msg_ring_flags = opcode_flags.bits();
         Entry(sqe)
     }
 }
@@ -0,0 +1,299 @@
+//! A pool of io_uring instances, one per thread.
+//!
+//! A [`RingPool`] is a set of rings created alike, meant to be moved to one thread per core.
+//! The rings get the same async worker limits, restrictions and registered files, and
+//! optionally share a single SQPOLL thread. Registered buffers and other resources are not
+//! shared.
+//!
+//! The async workers of io_uring belong to the task submitting the requests, that is the thread
+//! driving a ring, or its SQPOLL thread, so rings without SQPOLL have no io-wq backend to share.
+//! Each thread pins itself and its workers to the core of its ring with
+//! [`RingPool::init_thread`] or [`pin_thread`].
+
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::{io, mem, slice};
+
+use crate::register::{Probe, Restriction};
+use crate::{cqueue, squeue, sys, IoUring};
+
+/// A set of io_uring instances built by a [`Builder`].
+pub struct RingPool<S = squeue::Entry, C = cqueue::Entry>
+where
+    S: squeue::EntryMarker,
+    C: cqueue::EntryMarker,
+{
+    rings: Vec<IoUring<S, C>>,
+    cores: Vec<Option<usize>>,
+    probe: Probe,
+}
+
+/// Builder for a [`RingPool`].
+pub struct Builder<S = squeue::Entry, C = cqueue::Entry>
+where
+    S: squeue::EntryMarker,
+    C: cqueue::EntryMarker,
+{
+    ring: crate::Builder<S, C>,
+    cores: Vec<usize>,
+    sqpoll: Option<SqPoll>,
+    iowq_max_workers: Option<[u32; 2]>,
+    restrictions: Vec<Restriction>,
+    files: Files,
+}
+
+#[derive(Clone, Copy)]
+enum SqPoll {
+    Shared(u32),
+    PerRing(u32),
+}
+
+enum Files {
+    None,
+    Fds(Vec<RawFd>),
+    Sparse(u32),
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> RingPool<S, C> {
+    /// Create a [`Builder`] for a `RingPool`.
+    #[must_use]
+    pub fn builder() -> Builder<S, C> {
+        Builder {
+            ring: IoUring::builder(),
+            cores: Vec::new(),
+            sqpoll: None,
+            iowq_max_workers: None,
+            restrictions: Vec::new(),
+            files: Files::None,
+        }
+    }
+
+    /// The number of rings in the pool.
+    pub fn len(&self) -> usize {
+        self.rings.len()
+    }
+
+    /// Returns `true` if the pool has no rings.
+    pub fn is_empty(&self) -> bool {
+        self.rings.is_empty()
+    }
+
+    /// Get the ring at `index`.
+    pub fn get(&self, index: usize) -> Option<&IoUring<S, C>> {
+        self.rings.get(index)
+    }
+
+    /// Get the ring at `index` mutably.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut IoUring<S, C>> {
+        self.rings.get_mut(index)
+    }
+
+    /// The core the ring at `index` is meant to run on, if the pool was built with
+    /// [`cores`](Builder::cores).
+    pub fn core(&self, index: usize) -> Option<usize> {
+        self.cores.get(index).copied().flatten()
+    }
+
+    /// Pin the calling thread and its async workers to the core of the ring at `index`, see
+    /// [`pin_thread`]. Does nothing if the ring has no core.
+    ///
+    /// This must be called from the thread driving the ring.
+    pub fn init_thread(&self, index: usize) -> io::Result<()> {
+        match self.core(index) {
+            Some(core) => pin_thread(&self.rings[index], core),
+            None => Ok(()),
+        }
+    }
+
+    /// The features supported by the rings of the pool.
+    pub fn probe(&self) -> &Probe {
+        &self.probe
+    }
+
+    /// Iterate over the rings of the pool.
+    pub fn iter(&self) -> slice::Iter<'_, IoUring<S, C>> {
+        self.rings.iter()
+    }
+
+    /// Take the rings out of the pool, to move each of them to its thread.
+    ///
+    /// The thread driving the ring at `index` can then pin itself with [`pin_thread`], to the
+    /// core given by [`core`](Self::core).
+    pub fn into_rings(self) -> Vec<IoUring<S, C>> {
+        self.rings
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Builder<S, C> {
+    /// The builder every ring of the pool is created from.
+    ///
+    /// The pool adds the SQPOLL, `ATTACH_WQ` and `R_DISABLED` setup flags as needed.
+    pub fn ring(&mut self, ring: crate::Builder<S, C>) -> &mut Self {
+        self.ring = ring;
+        self
+    }
+
+    /// The cores to run the rings on, in turn.
+    ///
+    /// With [`sqpoll`](Self::sqpoll), the SQPOLL thread of each ring and its async workers are
+    /// pinned to its core when the pool is built. Otherwise the thread driving each ring pins
+    /// itself with [`RingPool::init_thread`]. A [`shared_sqpoll`](Self::shared_sqpoll) thread
+    /// can't be pinned to several cores, [`build`](Self::build) fails with `EINVAL` then.
+    pub fn cores(&mut self, cores: &[usize]) -> &mut Self {
+        self.cores = cores.to_vec();
+        self
+    }
+
+    /// Give every ring its own SQPOLL thread, pinned to its core if [`cores`](Self::cores) are
+    /// given.
+    pub fn sqpoll(&mut self, idle: u32) -> &mut Self {
+        self.sqpoll = Some(SqPoll::PerRing(idle));
+        self
+    }
+
+    /// Share a single SQPOLL thread between all the rings, by attaching them to the first one
+    /// with `ATTACH_WQ`.
+    pub fn shared_sqpoll(&mut self, idle: u32) -> &mut Self {
+        self.sqpoll = Some(SqPoll::Shared(idle));
+        self
+    }
+
+    /// Limit the number of bounded and unbounded async workers of every ring, see
+    /// [`register_iowq_max_workers`](crate::Submitter::register_iowq_max_workers).
+    pub fn iowq_max_workers(&mut self, max: [u32; 2]) -> &mut Self {
+        self.iowq_max_workers = Some(max);
+        self
+    }
+
+    /// Install the same allowlist on every ring, see
+    /// [`register_restrictions`](crate::Submitter::register_restrictions).
+    ///
+    /// When the rings have [`cores`](Self::cores) and no SQPOLL thread, the pool adds
+    /// `IORING_REGISTER_IOWQ_AFF` to the allowlist for [`RingPool::init_thread`].
+    pub fn restrictions(&mut self, restrictions: &[Restriction]) -> &mut Self {
+        self.restrictions = restrictions.to_vec();
+        self
+    }
+
+    /// Register the same files with every ring.
+    pub fn files(&mut self, fds: &[RawFd]) -> &mut Self {
+        self.files = Files::Fds(fds.to_vec());
+        self
+    }
+
+    /// Register the same number of empty file slots with every ring.
+    pub fn files_sparse(&mut self, nr: u32) -> &mut Self {
+        self.files = Files::Sparse(nr);
+        self
+    }
+
+    /// Build a pool of `size` rings, each with `entries` submission queue entries.
+    pub fn build(&self, size: usize, entries: u32) -> io::Result<RingPool<S, C>> {
+        let shared_sqpoll = matches!(self.sqpoll, Some(SqPoll::Shared(_)));
+        if size == 0 || (shared_sqpoll && !self.cores.is_empty()) {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+
+        let mut probe = Probe::new();
+        let mut rings: Vec<IoUring<S, C>> = Vec::with_capacity(size);
+        let mut cores = Vec::with_capacity(size);
+        for i in 0..size {
+            let core = match self.cores.len() {
+                0 => None,
+                n => Some(self.cores[i % n]),
+            };
+
+            let mut builder = self.ring.clone();
+            match self.sqpoll {
+                Some(SqPoll::PerRing(idle)) => {
+                    builder.setup_sqpoll(idle);
+                    if let Some(core) = core {
+                        builder.setup_sqpoll_cpu(core as u32);
+                    }
+                }
+                Some(SqPoll::Shared(idle)) => {
+                    builder.setup_sqpoll(idle);
+                }
+                None => (),
+            }
+            if let (Some(SqPoll::Shared(_)), Some(first)) = (self.sqpoll, rings.first()) {
+                builder.setup_attach_wq(first.as_raw_fd());
+            }
+            if !self.restrictions.is_empty() {
+                builder.setup_r_disabled();
+            }
+
+            let ring = builder.build(entries)?;
+            if i == 0 {
+                // Probed before the restrictions forbid it.
+                ring.submitter().register_probe(&mut probe)?;
+            }
+            self.setup(&ring, core)?;
+            rings.push(ring);
+            cores.push(core);
+        }
+
+        Ok(RingPool {
+            rings,
+            cores,
+            probe,
+        })
+    }
+
+    fn setup(&self, ring: &IoUring<S, C>, core: Option<usize>) -> io::Result<()> {
+        let submitter = ring.submitter();
+
+        // With SQPOLL, the affinity applies to the workers of the SQPOLL thread of the ring,
+        // otherwise to those of the calling thread, see `pin_thread`.
+        if let (Some(SqPoll::PerRing(_)), Some(core)) = (self.sqpoll, core) {
+            submitter.register_iowq_aff(&cpu_set(core))?;
+        }
+        if let Some(mut max) = self.iowq_max_workers {
+            submitter.register_iowq_max_workers(&mut max)?;
+        }
+        match &self.files {
+            Files::None => (),
+            Files::Fds(fds) => submitter.register_files(fds)?,
+            Files::Sparse(nr) => submitter.register_files_sparse(*nr)?,
+        }
+        if !self.restrictions.is_empty() {
+            let mut restrictions = self.restrictions.clone();
+            if self.sqpoll.is_none() && core.is_some() {
+                restrictions.push(Restriction::register_op(sys::IORING_REGISTER_IOWQ_AFF as _));
+            }
+            submitter.register_restrictions(&mut restrictions)?;
+            submitter.register_enable_rings()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Pin the calling thread, and the async workers of the requests it submits, to `core`.
+///
+/// The affinity of the workers is registered through `ring`, which the thread drives without
+/// SQPOLL. As the workers belong to the submitting thread rather than to a ring, it applies to
+/// every ring the thread drives without SQPOLL. Up to one pending entry of `ring` is submitted.
+pub fn pin_thread<S, C>(ring: &IoUring<S, C>, core: usize) -> io::Result<()>
+where
+    S: squeue::EntryMarker,
+    C: cqueue::EntryMarker,
+{
+    let set = cpu_set(core);
+    if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The thread has no workers until it submits to a ring. Entering with an entry to submit
+    // sets them up, even if the queue is empty.
+    let submitter = ring.submitter();
+    unsafe {
+        submitter.enter::<libc::sigset_t>(1, 0, 0, None)?;
+    }
+    submitter.register_iowq_aff(&set)
+}
+
+fn cpu_set(core: usize) -> libc::cpu_set_t {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    unsafe { libc::CPU_SET(core, &mut set) };
+    set
+}
@@ -3,7 +3,8 @@
 use std::os::unix::io::RawFd;
 use std::{fmt, io};
 
-use crate::sys;
+use crate::util::cast_ptr;
+use crate::{sys, types};
 
 pub(crate) fn execute(
     fd: RawFd,
@@ -14,6 +15,30 @@ pub(crate) fn execute(
     unsafe { sys::io_uring_register(fd, opcode, arg, len) }
 }
 
+/// Post a completion queue event with `user_data` and `result` to the ring referred to by
+/// `ring_fd`, like [`MsgRingData`](crate::opcode::MsgRingData) but without a ring to submit it
+/// from. This lets any thread wake up a ring without an eventfd. If `flags` is set, it is passed
+/// as the flags of the posted event.
+///
+/// Older kernels fail with `EBADF`. Available since 6.13.
+pub fn send_msg_ring(
+    ring_fd: RawFd,
+    user_data: u64,
+    result: i32,
+    flags: Option<u32>,
+) -> io::Result<()> {
+    let sqe = crate::opcode::MsgRingData::new(types::Fd(ring_fd), result, user_data, flags).build();
+
+    // Without a ring, the kernel reads the entry from the argument of the register call.
+    execute(
+        -1,
+        sys::IORING_REGISTER_SEND_MSG_RING,
+        cast_ptr(&sqe.0).cast(),
+        1,
+    )
+    .map(drop)
+}
+
 /// Information about what `io_uring` features the kernel supports.
 ///
 /// You can fill this in with [`register_probe`](crate::Submitter::register_probe).
@@ -84,6 +109,7 @@ impl fmt::Debug for Probe {
 
 /// An allowed feature of io_uring. You can set the allowed features with
 /// [`register_restrictions`](crate::Submitter::register_restrictions).
+#[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct Restriction(sys::io_uring_restriction);
 
@@ -128,6 +154,106 @@ impl Restriction {
     }
 }
 
+/// NAPI busy poll settings of an io_uring instance. You can set them with
+/// [`register_napi`](crate::Submitter::register_napi).
+///
+/// When enabled, waiting for completions busy polls the NAPI contexts of the network devices used
+/// by the ring's sockets for up to the busy poll timeout, instead of sleeping until an interrupt.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct Napi(sys::io_uring_napi);
+
+/// How the NAPI contexts to busy poll are collected, see [`Napi::tracking`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NapiTracking {
+    /// Track the NAPI contexts of the sockets used by requests automatically.
+    Dynamic,
+    /// Only busy poll the NAPI contexts added with
+    /// [`register_napi_id`](crate::Submitter::register_napi_id). Available since 6.12.
+    Static,
+    /// Do not track any NAPI context. This is only reported for rings without NAPI busy polling,
+    /// registering it fails with `EINVAL`.
+    Inactive,
+}
+
+impl Napi {
+    /// Create NAPI settings with dynamic tracking and no busy poll timeout.
+    pub fn new() -> Napi {
+        Napi(sys::io_uring_napi::default())
+    }
+
+    /// Set the busy poll timeout in microseconds.
+    pub fn busy_poll_timeout(mut self, usec: u32) -> Napi {
+        self.0.busy_poll_to = usec;
+        self
+    }
+
+    /// Set whether to prefer busy polling over interrupts, see `SO_PREFER_BUSY_POLL`.
+    pub fn prefer_busy_poll(mut self, prefer: bool) -> Napi {
+        self.0.prefer_busy_poll = prefer as _;
+        self
+    }
+
+    /// Set how the NAPI contexts to busy poll are collected.
+    pub fn tracking(mut self, tracking: NapiTracking) -> Napi {
+        self.0.op_param = match tracking {
+            NapiTracking::Dynamic => sys::IO_URING_NAPI_TRACKING_DYNAMIC,
+            NapiTracking::Static => sys::IO_URING_NAPI_TRACKING_STATIC,
+            NapiTracking::Inactive => sys::IO_URING_NAPI_TRACKING_INACTIVE,
+        };
+        self
+    }
+
+    /// The busy poll timeout in microseconds.
+    pub fn get_busy_poll_timeout(&self) -> u32 {
+        self.0.busy_poll_to
+    }
+
+    /// Whether busy polling is preferred over interrupts.
+    pub fn get_prefer_busy_poll(&self) -> bool {
+        self.0.prefer_busy_poll != 0
+    }
+
+    /// How the NAPI contexts to busy poll are collected.
+    pub fn get_tracking(&self) -> NapiTracking {
+        match self.0.op_param {
+            sys::IO_URING_NAPI_TRACKING_STATIC => NapiTracking::Static,
+            sys::IO_URING_NAPI_TRACKING_INACTIVE => NapiTracking::Inactive,
+            _ => NapiTracking::Dynamic,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn into_sys(self) -> sys::io_uring_napi {
+        sys::io_uring_napi {
+            opcode: sys::IO_URING_NAPI_REGISTER_OP as _,
+            ..self.0
+        }
+    }
+
+    #[inline]
+    pub(crate) fn from_sys(napi: sys::io_uring_napi) -> Napi {
+        Napi(sys::io_uring_napi { opcode: 0, ..napi })
+    }
+}
+
+impl Default for Napi {
+    #[inline]
+    fn default() -> Napi {
+        Napi::new()
+    }
+}
+
+impl fmt::Debug for Napi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Napi")
+            .field("busy_poll_timeout", &self.get_busy_poll_timeout())
+            .field("prefer_busy_poll", &self.get_prefer_busy_poll())
+            .field("tracking", &self.get_tracking())
+            .finish()
+    }
+}
+
 /// A RawFd, which can be used for
 /// [register_files_update](crate::Submitter::register_files_update).
 ///
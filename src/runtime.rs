@@ -0,0 +1,401 @@
+//! A minimal single-threaded reactor.
+//!
+//! A [`Reactor`] hands out a future for each pushed submission queue entry, which resolves to
+//! the matching completion queue entry. Multishot requests, whose completions are flagged with
+//! [`cqueue::more`], are instead returned as a [`Multishot`], a [`Stream`] yielding each
+//! completion in turn.
+//!
+//! Completions are only collected while waiting in [`Reactor::wait`] or [`Reactor::block_on`].
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::{fmt, io};
+
+use futures_core::Stream;
+
+use crate::squeue::PushError;
+use crate::{cqueue, squeue, IoUring};
+
+/// A single-threaded reactor driving an io_uring instance.
+///
+/// The reactor is a cheap handle, which can be cloned to be shared between tasks.
+pub struct Reactor<S = squeue::Entry, C = cqueue::Entry>
+where
+    S: squeue::EntryMarker,
+    C: cqueue::EntryMarker,
+{
+    inner: Rc<Inner<S, C>>,
+}
+
+struct Inner<S: squeue::EntryMarker, C: cqueue::EntryMarker> {
+    ring: RefCell<IoUring<S, C>>,
+    slots: RefCell<Slots<C>>,
+}
+
+// The `user_data` of a request is its key in the lower 32 bits and the generation of its slot in
+// the upper 32 bits, so that the completions the reactor did not create, or those of a request
+// which used the slot before, are not taken for its own.
+struct Slots<C> {
+    entries: Vec<Slot<C>>,
+    generations: Vec<u32>,
+    free: Vec<usize>,
+}
+
+enum Slot<C> {
+    Free,
+    Single {
+        result: Option<C>,
+        waker: Option<Waker>,
+    },
+    Multi {
+        queue: VecDeque<C>,
+        done: bool,
+        waker: Option<Waker>,
+    },
+    // The future or stream was dropped before the last completion.
+    Abandoned,
+}
+
+/// A future resolving to the completion of a request, see [`Reactor::push`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Op<S = squeue::Entry, C = cqueue::Entry>
+where
+    S: squeue::EntryMarker,
+    C: cqueue::EntryMarker,
+{
+    inner: Rc<Inner<S, C>>,
+    key: usize,
+    done: bool,
+}
+
+/// The completions of a multishot request, see [`Reactor::push_multishot`].
+pub struct Multishot<S = squeue::Entry, C = cqueue::Entry>
+where
+    S: squeue::EntryMarker,
+    C: cqueue::EntryMarker,
+{
+    inner: Rc<Inner<S, C>>,
+    key: usize,
+    done: bool,
+}
+
+/// A future resolving to the next completion of a [`Multishot`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Next<'a, S: squeue::EntryMarker, C: cqueue::EntryMarker> {
+    multishot: &'a mut Multishot<S, C>,
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Reactor<S, C> {
+    /// Create a reactor driving `ring`.
+    pub fn new(ring: IoUring<S, C>) -> Self {
+        Reactor {
+            inner: Rc::new(Inner {
+                ring: RefCell::new(ring),
+                slots: RefCell::new(Slots {
+                    entries: Vec::new(),
+                    generations: Vec::new(),
+                    free: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Push an entry to the submission queue, returning a future for its completion.
+    ///
+    /// The `user_data` of the entry is overwritten by the reactor.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of the entry (such as buffer) are valid and will
+    /// be valid for the entire duration of the operation, even if the returned future is
+    /// dropped before it completes.
+    pub unsafe fn push(&self, entry: squeue::Entry) -> Result<Op<S, C>, PushError> {
+        let key = self.inner.push(entry, false)?;
+        Ok(Op {
+            inner: self.inner.clone(),
+            key,
+            done: false,
+        })
+    }
+
+    /// Push a multishot entry to the submission queue, returning its completions.
+    ///
+    /// The `user_data` of the entry is overwritten by the reactor.
+    ///
+    /// # Safety
+    ///
+    /// See [`push`](Self::push).
+    pub unsafe fn push_multishot(
+        &self,
+        entry: squeue::Entry,
+    ) -> Result<Multishot<S, C>, PushError> {
+        let key = self.inner.push(entry, true)?;
+        Ok(Multishot {
+            inner: self.inner.clone(),
+            key,
+            done: false,
+        })
+    }
+
+    /// Submit the pushed entries, wait for at least `want` completions and wake the tasks
+    /// waiting for them.
+    pub fn wait(&self, want: usize) -> io::Result<()> {
+        self.inner.ring.borrow().submitter().submit_and_wait(want)?;
+        self.inner.dispatch();
+        Ok(())
+    }
+
+    /// Run a future to completion, waiting for completions whenever it is pending.
+    ///
+    /// The future must only wait for requests of this reactor, otherwise this may block
+    /// forever.
+    pub fn block_on<F: Future>(&self, future: F) -> io::Result<F::Output> {
+        let flag = Arc::new(Flag(AtomicBool::new(true)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+
+        loop {
+            if flag.0.swap(false, Ordering::Acquire) {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return Ok(output);
+                }
+            }
+            if !flag.0.load(Ordering::Acquire) {
+                self.wait(1)?;
+            }
+        }
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Clone for Reactor<S, C> {
+    fn clone(&self) -> Self {
+        Reactor {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> fmt::Debug for Reactor<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reactor").finish_non_exhaustive()
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Inner<S, C> {
+    unsafe fn push(&self, entry: squeue::Entry, multi: bool) -> Result<usize, PushError> {
+        let ring = self.ring.borrow();
+        let sq = ring.submission();
+        sq.sync();
+        if sq.is_full() {
+            return Err(PushError);
+        }
+
+        let mut slots = self.slots.borrow_mut();
+        let slot = if multi {
+            Slot::Multi {
+                queue: VecDeque::new(),
+                done: false,
+                waker: None,
+            }
+        } else {
+            Slot::Single {
+                result: None,
+                waker: None,
+            }
+        };
+        let key = match slots.free.pop() {
+            Some(key) => {
+                slots.entries[key] = slot;
+                key
+            }
+            None => {
+                slots.entries.push(slot);
+                slots.generations.push(1);
+                slots.entries.len() - 1
+            }
+        };
+        let user_data = u64::from(slots.generations[key]) << 32 | key as u64;
+
+        sq.push(&S::from(entry.user_data(user_data)))
+            .expect("queue is full");
+        sq.sync();
+
+        Ok(key)
+    }
+
+    // Hand the available completions to their futures.
+    fn dispatch(&self) {
+        let mut wakers = Vec::new();
+        {
+            let mut ring = self.ring.borrow_mut();
+            let (_, _, cq) = ring.split();
+            let mut slots = self.slots.borrow_mut();
+
+            for cqe in cq {
+                let (user_data, flags) = {
+                    let entry: cqueue::Entry = cqe.clone().into();
+                    (entry.user_data(), entry.flags())
+                };
+                let key = user_data as u32 as usize;
+                let more = cqueue::more(flags);
+
+                if slots.generations.get(key) != Some(&((user_data >> 32) as u32)) {
+                    continue;
+                }
+                match &mut slots.entries[key] {
+                    Slot::Single { result, waker } => {
+                        *result = Some(cqe);
+                        wakers.extend(waker.take());
+                    }
+                    Slot::Multi { queue, done, waker } => {
+                        queue.push_back(cqe);
+                        *done = !more;
+                        wakers.extend(waker.take());
+                    }
+                    Slot::Abandoned if !more => slots.release(key),
+                    Slot::Abandoned | Slot::Free => (),
+                }
+            }
+        }
+
+        // A woken task may use the reactor right away, so it is no longer borrowed.
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl<C> Slots<C> {
+    fn release(&mut self, key: usize) {
+        self.entries[key] = Slot::Free;
+        // Generation 0 is never used, so that small foreign `user_data` never match.
+        self.generations[key] = self.generations[key].checked_add(1).unwrap_or(1);
+        self.free.push(key);
+    }
+
+    // Release the slot of a dropped future, or mark it to be released on the last completion.
+    fn abandon(&mut self, key: usize) {
+        let finished = match &self.entries[key] {
+            Slot::Single { result, .. } => result.is_some(),
+            Slot::Multi { done, .. } => *done,
+            _ => false,
+        };
+        if finished {
+            self.release(key);
+        } else {
+            self.entries[key] = Slot::Abandoned;
+        }
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Future for Op<S, C> {
+    type Output = C;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<C> {
+        // Once completed, the slot may belong to another request.
+        if self.done {
+            return Poll::Pending;
+        }
+
+        let mut slots = self.inner.slots.borrow_mut();
+        let ready = match &mut slots.entries[self.key] {
+            Slot::Single { result, waker } => match result.take() {
+                Some(cqe) => Some(cqe),
+                None => {
+                    *waker = Some(cx.waker().clone());
+                    None
+                }
+            },
+            // The slot of a pending `Op` is always `Single`.
+            Slot::Multi { .. } | Slot::Abandoned | Slot::Free => None,
+        };
+
+        match ready {
+            Some(cqe) => {
+                slots.release(self.key);
+                drop(slots);
+                self.done = true;
+                Poll::Ready(cqe)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Drop for Op<S, C> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.inner.slots.borrow_mut().abandon(self.key);
+        }
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Multishot<S, C> {
+    /// Wait for the next completion, or `None` after the last one.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Next<'_, S, C> {
+        Next { multishot: self }
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Stream for Multishot<S, C> {
+    type Item = C;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<C>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        let mut slots = self.inner.slots.borrow_mut();
+        let (next, finished) = match &mut slots.entries[self.key] {
+            Slot::Multi { queue, done, waker } => match queue.pop_front() {
+                Some(cqe) => (Some(cqe), *done && queue.is_empty()),
+                None if *done => (None, true),
+                None => {
+                    *waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            },
+            // The slot of a `Multishot` that is not done is always `Multi`.
+            Slot::Single { .. } | Slot::Abandoned | Slot::Free => (None, false),
+        };
+
+        if finished {
+            slots.release(self.key);
+            drop(slots);
+            self.done = true;
+        }
+        Poll::Ready(next)
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Drop for Multishot<S, C> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.inner.slots.borrow_mut().abandon(self.key);
+        }
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Future for Next<'_, S, C> {
+    type Output = Option<C>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<C>> {
+        Pin::new(&mut *self.multishot).poll_next(cx)
+    }
+}
+
+struct Flag(AtomicBool);
+
+impl Wake for Flag {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::Release);
+    }
+}
@@ -0,0 +1,542 @@
+//! A safe submission layer.
+//!
+//! A [`Ring`] takes ownership of each submitted operation, together with the buffers and other
+//! resources the kernel accesses, and keeps it until the last completion of the operation. The
+//! operation is then handed back with its [`Completion`].
+//!
+//! Dropping a [`Ring`] with operations still in flight cancels them and waits up to a second
+//! for them to complete. If the kernel does not support synchronous cancellation (before 6.0),
+//! or the operations do not complete in time, the resources of the remaining operations are
+//! leaked instead of being freed while the kernel may still access them.
+//!
+//! Custom operations can be added by implementing [`Op`].
+
+use std::any::Any;
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+use std::time::{Duration, Instant};
+use std::{fmt, io, marker, mem};
+
+use crate::types::{self, sealed, CancelBuilder, Timespec};
+use crate::{cqueue, opcode, squeue, IoUring, Submitter};
+
+// How long dropping a `Ring` waits for its operations before leaking their resources.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An operation that owns the resources it uses.
+///
+/// # Safety
+///
+/// The entry returned by [`entry`](Self::entry) must only reference memory owned by the
+/// operation, which stays at the same address when the operation is moved (e.g. is
+/// heap-allocated) and stays valid until the operation is dropped.
+pub unsafe trait Op: marker::Send + 'static {
+    /// Build the submission queue entry. The `user_data` is set by the [`Ring`].
+    fn entry(&mut self) -> squeue::Entry;
+
+    /// Called with the result of the last completion, before the operation is handed back.
+    fn complete(&mut self, result: i32) {
+        let _ = result;
+    }
+}
+
+trait AnyOp: marker::Send {
+    fn complete(&mut self, result: i32);
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any + marker::Send>;
+}
+
+impl<T: Op> AnyOp for T {
+    fn complete(&mut self, result: i32) {
+        Op::complete(self, result)
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any + marker::Send> {
+        self
+    }
+}
+
+/// An io_uring instance which owns the resources of in-flight operations.
+pub struct Ring<S = squeue::Entry, C = cqueue::Entry>
+where
+    S: squeue::EntryMarker,
+    C: cqueue::EntryMarker,
+{
+    ring: IoUring<S, C>,
+    ops: Ops,
+}
+
+// The in-flight operations. The `user_data` of an operation is its key in the lower 32 bits and
+// the generation of its slot in the upper 32 bits, so that the completions the ring did not
+// create, or those of an operation which used the slot before, are not taken for its own.
+#[derive(Default)]
+struct Ops {
+    entries: Vec<Slot>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+struct Slot {
+    generation: u32,
+    op: Option<Box<dyn AnyOp>>,
+}
+
+impl Ops {
+    fn insert(&mut self, op: Box<dyn AnyOp>) -> u64 {
+        self.len += 1;
+        let key = match self.free.pop() {
+            Some(key) => key,
+            None => {
+                self.entries.push(Slot {
+                    generation: 1,
+                    op: None,
+                });
+                (self.entries.len() - 1) as u32
+            }
+        };
+        let slot = &mut self.entries[key as usize];
+        slot.op = Some(op);
+        u64::from(slot.generation) << 32 | u64::from(key)
+    }
+
+    fn next_user_data(&self) -> u64 {
+        match self.free.last() {
+            Some(&key) => u64::from(self.entries[key as usize].generation) << 32 | u64::from(key),
+            None => 1 << 32 | self.entries.len() as u64,
+        }
+    }
+
+    fn release(&mut self, user_data: u64, result: i32) -> Option<Box<dyn Any + marker::Send>> {
+        let key = user_data as u32;
+        let slot = self.entries.get_mut(key as usize)?;
+        if slot.generation != (user_data >> 32) as u32 {
+            return None;
+        }
+        let mut op = slot.op.take()?;
+        // Generation 0 is never used, so that small foreign `user_data` never match.
+        slot.generation = slot.generation.checked_add(1).unwrap_or(1);
+        self.free.push(key);
+        self.len -= 1;
+
+        op.complete(result);
+        Some(op.into_any())
+    }
+}
+
+/// The identifier of an operation pushed to a [`Ring`], used as its `user_data`.
+///
+/// The `user_data` is the slot of the operation in its lower 32 bits and a generation in its
+/// upper 32 bits, slots are reused once an operation has completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpId(u64);
+
+impl OpId {
+    /// The `user_data` of the operation, e.g. to target it with
+    /// [`AsyncCancel`](crate::opcode::AsyncCancel).
+    pub fn user_data(self) -> u64 {
+        self.0
+    }
+}
+
+/// A completion of an operation pushed to a [`Ring`].
+pub struct Completion {
+    id: OpId,
+    result: i32,
+    flags: u32,
+    op: Option<Box<dyn Any + marker::Send>>,
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Ring<S, C> {
+    /// Wrap an io_uring instance.
+    pub fn new(ring: IoUring<S, C>) -> Self {
+        Ring {
+            ring,
+            ops: Ops::default(),
+        }
+    }
+
+    /// Get the submitter of the io_uring instance.
+    #[inline]
+    pub fn submitter(&self) -> Submitter<'_> {
+        self.ring.submitter()
+    }
+
+    /// Get the parameters of the io_uring instance.
+    #[inline]
+    pub fn params(&self) -> &crate::Parameters {
+        self.ring.params()
+    }
+
+    /// The number of operations that have not completed yet.
+    #[inline]
+    pub fn in_flight(&self) -> usize {
+        self.ops.len
+    }
+
+    /// Push an operation to the submission queue, taking ownership of it until it completes.
+    ///
+    /// If the queue is full, the operation is handed back.
+    pub fn push<T: Op>(&mut self, mut op: T) -> Result<OpId, T> {
+        let sq = self.ring.submission();
+        sq.sync();
+        if sq.is_full() {
+            return Err(op);
+        }
+
+        let id = OpId(self.ops.next_user_data());
+        let entry = S::from(op.entry().user_data(id.0));
+
+        // SAFETY: by the contract of `Op`, the entry only references memory owned by `op`,
+        // which is kept until its last completion or until the ring has been drained.
+        unsafe {
+            sq.push(&entry).expect("queue is full");
+        }
+        sq.sync();
+
+        let user_data = self.ops.insert(Box::new(op));
+        debug_assert_eq!(user_data, id.0);
+
+        Ok(id)
+    }
+
+    /// Submit all pushed operations to the kernel.
+    #[inline]
+    pub fn submit(&self) -> io::Result<usize> {
+        self.ring.submit()
+    }
+
+    /// Submit all pushed operations to the kernel and wait for at least `want` completions.
+    #[inline]
+    pub fn submit_and_wait(&self, want: usize) -> io::Result<usize> {
+        self.ring.submit_and_wait(want)
+    }
+
+    /// Take the available completions.
+    ///
+    /// Completions the ring did not create, such as those posted by
+    /// [`MsgRingData`](crate::opcode::MsgRingData) from another ring, are returned without an
+    /// operation and leave the operations in flight untouched.
+    pub fn completions(&mut self) -> Vec<Completion> {
+        let cq = self.ring.completion();
+        let mut completions = Vec::with_capacity(cq.len());
+
+        for entry in cq {
+            let entry: cqueue::Entry = entry.into();
+            let op = if cqueue::more(entry.flags()) {
+                None
+            } else {
+                self.ops.release(entry.user_data(), entry.result())
+            };
+
+            completions.push(Completion {
+                id: OpId(entry.user_data()),
+                result: entry.result(),
+                flags: entry.flags(),
+                op,
+            });
+        }
+
+        completions
+    }
+
+    // Wait for all operations to complete, after cancelling them. Gives up with `ETIME` once
+    // `DRAIN_TIMEOUT` has passed.
+    fn drain(&mut self) -> io::Result<()> {
+        let deadline = Instant::now() + DRAIN_TIMEOUT;
+
+        // Entries not yet consumed by the kernel cannot be cancelled.
+        while {
+            let sq = self.ring.submission();
+            sq.sync();
+            !sq.is_empty()
+        } {
+            self.wait_until(deadline)?;
+        }
+
+        let timeout = Timespec::from(deadline.saturating_duration_since(Instant::now()));
+        match self
+            .submitter()
+            .register_sync_cancel(Some(timeout), CancelBuilder::any())
+        {
+            Ok(()) => (),
+            Err(ref err) if err.raw_os_error() == Some(libc::ENOENT) => (),
+            Err(err) => return Err(err),
+        }
+
+        while self.ops.len > 0 {
+            self.wait_until(deadline)?;
+        }
+
+        Ok(())
+    }
+
+    // Submit and wait for a completion until `deadline`, then release the completed operations.
+    fn wait_until(&mut self, deadline: Instant) -> io::Result<()> {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(io::Error::from_raw_os_error(libc::ETIME));
+        }
+
+        let timeout = Timespec::from(deadline - now);
+        let args = types::SubmitArgs::new().timespec(&timeout);
+        match self.ring.submitter().submit_with_args(1, &args) {
+            Ok(_) => (),
+            Err(ref err)
+                if matches!(
+                    err.raw_os_error(),
+                    Some(libc::ETIME | libc::EINTR | libc::EBUSY)
+                ) => {}
+            Err(err) => return Err(err),
+        }
+
+        self.completions();
+        Ok(())
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> Drop for Ring<S, C> {
+    fn drop(&mut self) {
+        if self.ops.len > 0 && self.drain().is_err() {
+            // The kernel may still access the resources.
+            mem::forget(mem::take(&mut self.ops.entries));
+        }
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> fmt::Debug for Ring<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ring")
+            .field("params", self.ring.params())
+            .field("in_flight", &self.ops.len)
+            .finish()
+    }
+}
+
+impl<S: squeue::EntryMarker, C: cqueue::EntryMarker> AsRawFd for Ring<S, C> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.ring.as_raw_fd()
+    }
+}
+
+impl Completion {
+    /// The identifier of the operation.
+    #[inline]
+    pub fn id(&self) -> OpId {
+        self.id
+    }
+
+    /// The result of the operation, see [`cqueue::Entry::result`].
+    #[inline]
+    pub fn result(&self) -> i32 {
+        self.result
+    }
+
+    /// The flags of the completion, see [`cqueue::Entry::flags`].
+    #[inline]
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Take back the operation, if this is its last completion and it is of type `T`.
+    pub fn take<T: Op>(&mut self) -> Option<T> {
+        match self.op.take()?.downcast::<T>() {
+            Ok(op) => Some(*op),
+            Err(op) => {
+                self.op = Some(op);
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Completion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Completion")
+            .field("id", &self.id)
+            .field("result", &self.result)
+            .field("flags", &self.flags)
+            .field("last", &self.op.is_some())
+            .finish()
+    }
+}
+
+/// Do not perform any I/O, see [`opcode::Nop`].
+#[derive(Debug, Default)]
+pub struct Nop;
+
+unsafe impl Op for Nop {
+    fn entry(&mut self) -> squeue::Entry {
+        opcode::Nop::new().build()
+    }
+}
+
+/// Read into the spare capacity of a buffer, see [`opcode::Read`].
+///
+/// The length of the buffer is extended by the number of bytes read.
+#[derive(Debug)]
+pub struct Read {
+    fd: sealed::Target,
+    buf: Vec<u8>,
+    offset: u64,
+}
+
+impl Read {
+    pub fn new(fd: impl sealed::UseFixed, buf: Vec<u8>, offset: u64) -> Self {
+        Read {
+            fd: fd.into(),
+            buf,
+            offset,
+        }
+    }
+
+    /// Take the buffer.
+    pub fn into_buf(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+unsafe impl Op for Read {
+    fn entry(&mut self) -> squeue::Entry {
+        let spare = self.buf.spare_capacity_mut();
+        opcode::Read::new(self.fd, spare.as_mut_ptr().cast(), spare.len() as _)
+            .offset(self.offset)
+            .build()
+    }
+
+    fn complete(&mut self, result: i32) {
+        if result > 0 {
+            // SAFETY: the kernel initialized `result` bytes of the spare capacity.
+            unsafe { self.buf.set_len(self.buf.len() + result as usize) };
+        }
+    }
+}
+
+/// Write the contents of a buffer, see [`opcode::Write`].
+#[derive(Debug)]
+pub struct Write {
+    fd: sealed::Target,
+    buf: Vec<u8>,
+    offset: u64,
+}
+
+impl Write {
+    pub fn new(fd: impl sealed::UseFixed, buf: Vec<u8>, offset: u64) -> Self {
+        Write {
+            fd: fd.into(),
+            buf,
+            offset,
+        }
+    }
+
+    /// Take the buffer.
+    pub fn into_buf(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+unsafe impl Op for Write {
+    fn entry(&mut self) -> squeue::Entry {
+        opcode::Write::new(self.fd, self.buf.as_ptr(), self.buf.len() as _)
+            .offset(self.offset)
+            .build()
+    }
+}
+
+/// Receive into the spare capacity of a buffer, see [`opcode::Recv`].
+///
+/// The length of the buffer is extended by the number of bytes received.
+#[derive(Debug)]
+pub struct Recv {
+    fd: sealed::Target,
+    buf: Vec<u8>,
+}
+
+impl Recv {
+    pub fn new(fd: impl sealed::UseFixed, buf: Vec<u8>) -> Self {
+        Recv { fd: fd.into(), buf }
+    }
+
+    /// Take the buffer.
+    pub fn into_buf(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+unsafe impl Op for Recv {
+    fn entry(&mut self) -> squeue::Entry {
+        let spare = self.buf.spare_capacity_mut();
+        opcode::Recv::new(self.fd, spare.as_mut_ptr().cast(), spare.len() as _).build()
+    }
+
+    fn complete(&mut self, result: i32) {
+        if result > 0 {
+            // SAFETY: the kernel initialized `result` bytes of the spare capacity.
+            unsafe { self.buf.set_len(self.buf.len() + result as usize) };
+        }
+    }
+}
+
+/// Send the contents of a buffer, see [`opcode::Send`].
+#[derive(Debug)]
+pub struct Send {
+    fd: sealed::Target,
+    buf: Vec<u8>,
+}
+
+impl Send {
+    pub fn new(fd: impl sealed::UseFixed, buf: Vec<u8>) -> Self {
+        Send { fd: fd.into(), buf }
+    }
+
+    /// Take the buffer.
+    pub fn into_buf(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+unsafe impl Op for Send {
+    fn entry(&mut self) -> squeue::Entry {
+        opcode::Send::new(self.fd, self.buf.as_ptr(), self.buf.len() as _).build()
+    }
+}
+
+/// Complete after a relative timeout, see [`opcode::Timeout`].
+///
+/// The operation completes with `-ETIME` when the timeout expires.
+#[derive(Debug)]
+pub struct Timeout {
+    timespec: Box<Timespec>,
+}
+
+impl Timeout {
+    pub fn new(timespec: Timespec) -> Self {
+        Timeout {
+            timespec: Box::new(timespec),
+        }
+    }
+}
+
+unsafe impl Op for Timeout {
+    fn entry(&mut self) -> squeue::Entry {
+        opcode::Timeout::new(&*self.timespec).build()
+    }
+}
+
+/// Close a file descriptor, taking ownership of it, see [`opcode::Close`].
+#[derive(Debug)]
+pub struct Close {
+    fd: RawFd,
+}
+
+impl Close {
+    pub fn new(fd: impl IntoRawFd) -> Self {
+        Close {
+            fd: fd.into_raw_fd(),
+        }
+    }
+}
+
+unsafe impl Op for Close {
+    fn entry(&mut self) -> squeue::Entry {
+        opcode::Close::new(types::Fd(self.fd)).build()
+    }
+}
@@ -1,11 +1,13 @@
 //! Submission Queue
 
+use std::collections::VecDeque;
 use std::fmt::{self, Debug, Display, Formatter};
-use std::{cell::Cell, error::Error, mem, sync::atomic};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::{cell::Cell, cmp, error::Error, mem, ptr, sync::atomic};
 
 use bitflags::bitflags;
 
-use crate::{sys, util::private, util::unsync_load, util::Mmap};
+use crate::{sys, types, util::private, util::unsync_load};
 
 pub(crate) struct Inner<E: EntryMarker> {
     pub(crate) head: *const atomic::AtomicU32,
@@ -119,23 +121,26 @@ bitflags! {
 impl<E: EntryMarker> Inner<E> {
     #[rustfmt::skip]
     pub(crate) unsafe fn new(
-        sq_mmap: &Mmap,
-        sqe_mmap: &Mmap,
+        sq_ring: *mut libc::c_void,
+        sqes: *mut libc::c_void,
         p: &sys::io_uring_params,
     ) -> Self {
-        let head         = sq_mmap.offset(p.sq_off.head        ) as *const atomic::AtomicU32;
-        let tail         = sq_mmap.offset(p.sq_off.tail        ) as *const atomic::AtomicU32;
-        let ring_mask    = sq_mmap.offset(p.sq_off.ring_mask   ).cast::<u32>().read();
-        let ring_entries = sq_mmap.offset(p.sq_off.ring_entries).cast::<u32>().read();
-        let flags        = sq_mmap.offset(p.sq_off.flags       ) as *const atomic::AtomicU32;
-        let dropped      = sq_mmap.offset(p.sq_off.dropped     ) as *const atomic::AtomicU32;
-        let array        = sq_mmap.offset(p.sq_off.array       ) as *mut u32;
-
-        let sqes         = sqe_mmap.as_mut_ptr() as *mut E;
-
-        // To keep it simple, map it directly to `sqes`.
-        for i in 0..ring_entries {
-            array.add(i as usize).write_volatile(i);
+        let head         = sq_ring.add(p.sq_off.head         as usize) as *const atomic::AtomicU32;
+        let tail         = sq_ring.add(p.sq_off.tail         as usize) as *const atomic::AtomicU32;
+        let ring_mask    = sq_ring.add(p.sq_off.ring_mask    as usize).cast::<u32>().read();
+        let ring_entries = sq_ring.add(p.sq_off.ring_entries as usize).cast::<u32>().read();
+        let flags        = sq_ring.add(p.sq_off.flags        as usize) as *const atomic::AtomicU32;
+        let dropped      = sq_ring.add(p.sq_off.dropped      as usize) as *const atomic::AtomicU32;
+
+        let sqes         = sqes as *mut E;
+
+        // Without `IORING_SETUP_NO_SQARRAY`, the kernel finds the entries through the indirection
+        // array. To keep it simple, map it directly to `sqes`.
+        if p.flags & sys::IORING_SETUP_NO_SQARRAY == 0 {
+            let array = sq_ring.add(p.sq_off.array as usize) as *mut u32;
+            for i in 0..ring_entries {
+                array.add(i as usize).write_volatile(i);
+            }
         }
 
         let ring_entries = ring_entries as usize;
@@ -155,6 +160,22 @@ impl<E: EntryMarker> Inner<E> {
         }
     }
 
+    /// Move the entries pushed to `old` but not yet synchronized into this queue, after the rings
+    /// were resized. The kernel has already moved the synchronized ones.
+    pub(crate) unsafe fn copy_unsynced_from(&self, old: &Inner<E>) {
+        let local_tail = old.local_tail.get();
+        let mut tail = unsync_load(old.tail);
+
+        while tail != local_tail {
+            let src = old.sqes.add((tail & old.ring_mask) as usize);
+            let dst = self.sqes.add((tail & self.ring_mask) as usize);
+            ptr::copy_nonoverlapping(src, dst, 1);
+            tail = tail.wrapping_add(1);
+        }
+
+        self.local_tail.set(local_tail);
+    }
+
     #[inline]
     pub(crate) fn borrow(&self) -> SubmissionQueue<'_, E> {
         SubmissionQueue { queue: self }
@@ -355,6 +376,45 @@ impl<E: EntryMarker> SubmissionQueue<'_, E> {
         Ok(())
     }
 
+    /// Attempts to push the entries of a [`Chain`] into the queue.
+    /// If the queue does not have space for all of the entries, an error is returned.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of all the entries (such as buffer), including the
+    /// timespecs of link timeouts, are valid and will be valid for the entire duration of the
+    /// operation, otherwise it may cause memory problems.
+    pub unsafe fn push_chain(&self, chain: &Chain<'_>) -> Result<(), PushError> {
+        if self.capacity() - self.len() < chain.len() {
+            return Err(PushError);
+        }
+
+        for entry in chain.entries() {
+            self.push_unchecked(&E::from(entry));
+        }
+
+        Ok(())
+    }
+
+    /// Move the entries staged in a [`SqHandle`] into the queue, in the order they were pushed
+    /// to the handle, until the queue is full. Returns the number of moved entries.
+    ///
+    /// The entries are only visible to the kernel after the next [`sync`](Self::sync).
+    pub fn push_staged<T>(&self, handle: &SqHandle<T>) -> usize
+    where
+        T: EntryMarker,
+        E: From<T>,
+    {
+        let mut staged = handle.lock();
+        let count = cmp::min(self.capacity() - self.len(), staged.len());
+        for entry in staged.drain(..count) {
+            // Safety: the validity of the entry was promised to `SqHandle::push`.
+            unsafe { self.push_unchecked(&E::from(entry)) };
+        }
+
+        count
+    }
+
     #[inline]
     unsafe fn push_unchecked(&self, entry: &E) {
         *self
@@ -367,6 +427,316 @@ impl<E: EntryMarker> SubmissionQueue<'_, E> {
     }
 }
 
+/// A handle to stage submission queue entries from several threads.
+///
+/// The submission queue has a single producer, the thread owning the [`SubmissionQueue`]. A
+/// `SqHandle` can be cloned and shared with other threads, which push entries into a queue
+/// guarded by a short lock. The owner then moves them into the submission queue with
+/// [`SubmissionQueue::push_staged`] and submits them.
+///
+/// Entries pushed by one thread are moved into the submission queue in the order they were
+/// pushed. Entries pushed by different threads are ordered by when they acquired the lock, and
+/// none of them are seen by the kernel before the owner moves and submits them.
+///
+/// ```no_run
+/// # use ntex_io_uring::{opcode, squeue::SqHandle, IoUring};
+/// # fn main() -> std::io::Result<()> {
+/// let ring = IoUring::new(8)?;
+/// let handle = SqHandle::new(64);
+///
+/// std::thread::scope(|s| {
+///     let worker = handle.clone();
+///     s.spawn(move || unsafe {
+///         worker.push(opcode::Nop::new().build()).expect("handle is full");
+///     });
+/// });
+///
+/// let sq = ring.submission();
+/// sq.push_staged(&handle);
+/// sq.sync();
+/// ring.submit()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SqHandle<E: EntryMarker = Entry> {
+    inner: Arc<SqHandleInner<E>>,
+}
+
+struct SqHandleInner<E> {
+    staged: Mutex<VecDeque<E>>,
+    capacity: usize,
+}
+
+impl<E: EntryMarker> SqHandle<E> {
+    /// Create a handle which stages at most `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        SqHandle {
+            inner: Arc::new(SqHandleInner {
+                staged: Mutex::new(VecDeque::with_capacity(capacity)),
+                capacity,
+            }),
+        }
+    }
+
+    /// Stage an entry to be moved into the submission queue.
+    /// If the handle is full, an error is returned.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of the entry (such as buffer) are valid and will
+    /// be valid for the entire duration of the operation, otherwise it may cause memory problems.
+    pub unsafe fn push(&self, entry: E) -> Result<(), PushError> {
+        let mut staged = self.lock();
+        if staged.len() >= self.inner.capacity {
+            return Err(PushError);
+        }
+        staged.push_back(entry);
+        Ok(())
+    }
+
+    /// The maximum number of staged entries.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    /// The number of staged entries.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if no entries are staged.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<E>> {
+        // The staged entries are plain data, which a panicking thread can't leave half-written.
+        self.inner
+            .staged
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<E: EntryMarker> Clone for SqHandle<E> {
+    fn clone(&self) -> Self {
+        SqHandle {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<E: EntryMarker> Debug for SqHandle<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqHandle")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+/// A chain of linked entries, which are started one after the other.
+///
+/// The links are set up with [`IO_LINK`](Flags::IO_LINK) or, with [`hard`](Self::hard),
+/// [`IO_HARDLINK`](Flags::IO_HARDLINK), and each step can be given a deadline with a
+/// [`LinkTimeout`](crate::opcode::LinkTimeout), whose timespec the chain borrows. The entries are given consecutive `user_data`,
+/// so that [`failure`](Self::failure) can tell which step broke the chain from the completions.
+///
+/// ```no_run
+/// # use ntex_io_uring::{opcode, squeue::Chain, types, IoUring};
+/// # fn main() -> std::io::Result<()> {
+/// # let ring = IoUring::new(8)?;
+/// let ts = types::Timespec::new().sec(1);
+/// let chain = Chain::new(0x100)
+///     .push(opcode::Nop::new().build())
+///     .push_with_timeout(opcode::Nop::new().build(), &ts);
+///
+/// unsafe { ring.submission().push_chain(&chain).expect("queue is full") };
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Chain<'a> {
+    user_data: u64,
+    hard: bool,
+    steps: Vec<(Entry, Option<&'a types::Timespec>)>,
+}
+
+/// The reason a [`Chain`] was broken, see [`Chain::failure`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainFailure {
+    /// The step completed with an error or a short transfer, or it was cancelled.
+    Failed { step: usize, result: i32 },
+    /// The link timeout of the step expired.
+    TimedOut { step: usize },
+}
+
+impl<'a> Chain<'a> {
+    /// Create an empty chain, whose entries get consecutive `user_data` starting at
+    /// `user_data`.
+    pub fn new(user_data: u64) -> Self {
+        Chain {
+            user_data,
+            hard: false,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Link the entries with [`IO_HARDLINK`](Flags::IO_HARDLINK), so that the chain is not
+    /// broken when a step fails.
+    pub fn hard(mut self) -> Self {
+        self.hard = true;
+        self
+    }
+
+    /// Add a step to the chain.
+    pub fn push(mut self, entry: Entry) -> Self {
+        self.steps.push((entry, None));
+        self
+    }
+
+    /// Add a step to the chain, which is cancelled if it hasn't completed after `timespec`.
+    pub fn push_with_timeout(mut self, entry: Entry, timespec: &'a types::Timespec) -> Self {
+        self.steps.push((entry, Some(timespec)));
+        self
+    }
+
+    /// The number of entries in the chain, including link timeouts.
+    pub fn len(&self) -> usize {
+        self.steps
+            .iter()
+            .map(|(_, timeout)| 1 + timeout.is_some() as usize)
+            .sum()
+    }
+
+    /// Returns `true` if the chain has no steps.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// The `user_data` of a step, or of its link timeout if `timeout` is `true`.
+    pub fn user_data_of(&self, step: usize, timeout: bool) -> Option<u64> {
+        let index = self
+            .steps
+            .iter()
+            .enumerate()
+            .flat_map(|(step, (_, timeout))| {
+                std::iter::once((step, false)).chain(timeout.map(|_| (step, true)))
+            })
+            .position(|entry| entry == (step, timeout))?;
+        Some(self.user_data.wrapping_add(index as u64))
+    }
+
+    /// The entries of the chain, with their links and `user_data` set.
+    pub fn entries(&self) -> Vec<Entry> {
+        let link = if self.hard {
+            Flags::IO_HARDLINK
+        } else {
+            Flags::IO_LINK
+        };
+        let mut entries = Vec::with_capacity(self.len());
+        for (entry, timeout) in &self.steps {
+            entries.push(entry.clone().flags(link));
+            if let Some(timespec) = timeout {
+                entries.push(
+                    crate::opcode::LinkTimeout::new(*timespec as *const _)
+                        .build()
+                        .flags(link),
+                );
+            }
+        }
+
+        // the last entry ends the chain
+        if let Some(last) = entries.last_mut() {
+            last.0.flags &= !link.bits();
+        }
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.set_user_data(self.user_data.wrapping_add(i as u64));
+        }
+
+        entries
+    }
+
+    /// Find which step broke the chain, given the completions of its entries.
+    ///
+    /// A step cancelled right after a short read, write, splice or tee (or a send or receive
+    /// with `MSG_WAITALL`) is blamed on that short step. Any other cancelled step is reported
+    /// itself, with `-ECANCELED`.
+    ///
+    /// Completions of other requests are ignored, and `None` is returned if no step failed
+    /// among the given completions.
+    pub fn failure<'c, I>(&self, cqes: I) -> Option<ChainFailure>
+    where
+        I: IntoIterator<Item = &'c crate::cqueue::Entry>,
+    {
+        let len = self.len();
+        let mut results = vec![None; len];
+        for cqe in cqes {
+            let index = cqe.user_data().wrapping_sub(self.user_data);
+            if index < len as u64 {
+                results[index as usize] = Some(cqe.result());
+            }
+        }
+
+        let mut previous: Option<(usize, i32, Option<i32>)> = None;
+        let mut position = 0;
+        for (step, (entry, timeout)) in self.steps.iter().enumerate() {
+            let result = results[position];
+            let timed_out = timeout.is_some() && results[position + 1] == Some(-libc::ETIME);
+            position += 1 + timeout.is_some() as usize;
+
+            let result = match result {
+                Some(result) => result,
+                None => continue,
+            };
+            if result == -libc::ECANCELED {
+                if timed_out {
+                    return Some(ChainFailure::TimedOut { step });
+                }
+                // cancelled because the previous step was short, or by another request
+                return match previous {
+                    Some((previous, short, Some(expected))) if short < expected => {
+                        Some(ChainFailure::Failed {
+                            step: previous,
+                            result: short,
+                        })
+                    }
+                    _ => Some(ChainFailure::Failed { step, result }),
+                };
+            }
+            if result < 0 {
+                return Some(ChainFailure::Failed { step, result });
+            }
+            previous = Some((step, result, expected_len(entry)));
+        }
+
+        None
+    }
+}
+
+// The number of bytes a step must transfer for the chain to go on, for the requests that
+// break the chain when short.
+fn expected_len(entry: &Entry) -> Option<i32> {
+    use crate::opcode;
+
+    let sqe = &entry.0;
+    let len = i32::try_from(sqe.len).ok()?;
+    match sqe.opcode {
+        opcode::Read::CODE
+        | opcode::Write::CODE
+        | opcode::ReadFixed::CODE
+        | opcode::WriteFixed::CODE
+        | opcode::Splice::CODE
+        | opcode::Tee::CODE => Some(len),
+        opcode::Send::CODE | opcode::Recv::CODE => {
+            // SAFETY: both opcodes set `msg_flags`.
+            let flags = unsafe { sqe.__bindgen_anon_3.msg_flags };
+            (flags & libc::MSG_WAITALL as u32 != 0).then_some(len)
+        }
+        _ => None,
+    }
+}
+
 impl Entry {
     /// Set the submission event's [flags](Flags).
     #[inline]
@@ -384,6 +754,9 @@ impl Entry {
 
     /// Set the user data. This is an application-supplied value that will be passed straight
     /// through into the [completion queue entry](crate::cqueue::Entry::user_data).
+    ///
+    /// [`WAIT_TIMEOUT_USER_DATA`](types::WAIT_TIMEOUT_USER_DATA) (`u64::MAX`) is reserved for
+    /// [`IoUring::wait`](crate::IoUring::wait) on kernels without `EXT_ARG`.
     #[inline]
     pub fn user_data(mut self, user_data: u64) -> Entry {
         self.0.user_data = user_data;
@@ -463,6 +836,9 @@ impl Entry128 {
 
     /// Set the user data. This is an application-supplied value that will be passed straight
     /// through into the [completion queue entry](crate::cqueue::Entry::user_data).
+    ///
+    /// [`WAIT_TIMEOUT_USER_DATA`](types::WAIT_TIMEOUT_USER_DATA) (`u64::MAX`) is reserved for
+    /// [`IoUring::wait`](crate::IoUring::wait) on kernels without `EXT_ARG`.
     #[inline]
     pub fn user_data(mut self, user_data: u64) -> Entry128 {
         self.0 .0.user_data = user_data;
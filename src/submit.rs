@@ -1,8 +1,9 @@
+use std::ops::{Bound, RangeBounds};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic;
 use std::{io, mem, ptr};
 
-use crate::register::{execute, Probe};
+use crate::register::{execute, Napi, Probe};
 use crate::sys;
 use crate::types::{CancelBuilder, Timespec};
 use crate::util::{cast_ptr, OwnedFd};
@@ -44,6 +45,76 @@ bitflags!(
     }
 );
 
+/// Marks a ring whose file descriptor is not registered with `IORING_REGISTER_RING_FDS`.
+const NO_RING_INDEX: u32 = u32::MAX;
+
+/// The index of a ring file descriptor registered with `IORING_REGISTER_RING_FDS`, which is only
+/// valid in the table of the registering thread.
+pub(crate) struct RingIndex {
+    index: atomic::AtomicU32,
+    // The token of the registering thread, or 0.
+    owner: atomic::AtomicU64,
+}
+
+// Tokens are never reused, unlike the addresses of thread locals, so that a thread cannot take
+// the index of a thread that exited for its own.
+static NEXT_THREAD_TOKEN: atomic::AtomicU64 = atomic::AtomicU64::new(1);
+
+thread_local! {
+    static THREAD_TOKEN: u64 = NEXT_THREAD_TOKEN.fetch_add(1, atomic::Ordering::Relaxed);
+}
+
+fn thread_token() -> Option<u64> {
+    THREAD_TOKEN.try_with(|token| *token).ok()
+}
+
+impl RingIndex {
+    pub(crate) fn new() -> Self {
+        RingIndex {
+            index: atomic::AtomicU32::new(NO_RING_INDEX),
+            owner: atomic::AtomicU64::new(0),
+        }
+    }
+
+    // The index, if it was registered by the calling thread.
+    fn get(&self) -> Option<u32> {
+        if self.owner.load(atomic::Ordering::Acquire) != thread_token()? {
+            return None;
+        }
+        match self.index.load(atomic::Ordering::Relaxed) {
+            NO_RING_INDEX => None,
+            index => Some(index),
+        }
+    }
+
+    // Make the calling thread the owner, unless a thread already is.
+    fn claim(&self) -> bool {
+        match thread_token() {
+            Some(token) => self
+                .owner
+                .compare_exchange(
+                    0,
+                    token,
+                    atomic::Ordering::AcqRel,
+                    atomic::Ordering::Acquire,
+                )
+                .is_ok(),
+            None => false,
+        }
+    }
+
+    // Only called by the owner, after `claim`.
+    fn set(&self, index: u32) {
+        self.index.store(index, atomic::Ordering::Relaxed);
+    }
+
+    // Only called by the owner.
+    fn clear(&self) {
+        self.index.store(NO_RING_INDEX, atomic::Ordering::Relaxed);
+        self.owner.store(0, atomic::Ordering::Release);
+    }
+}
+
 /// Interface for submitting submission queue events in an io_uring instance to the kernel for
 /// executing and registering files or buffers with the instance.
 ///
@@ -52,6 +123,7 @@ bitflags!(
 pub struct Submitter<'a> {
     fd: &'a OwnedFd,
     params: &'a Parameters,
+    ring_index: &'a RingIndex,
 
     sq_head: *const atomic::AtomicU32,
     sq_tail: *const atomic::AtomicU32,
@@ -63,6 +135,7 @@ impl<'a> Submitter<'a> {
     pub(crate) const fn new(
         fd: &'a OwnedFd,
         params: &'a Parameters,
+        ring_index: &'a RingIndex,
         sq_head: *const atomic::AtomicU32,
         sq_tail: *const atomic::AtomicU32,
         sq_flags: *const atomic::AtomicU32,
@@ -70,12 +143,19 @@ impl<'a> Submitter<'a> {
         Submitter {
             fd,
             params,
+            ring_index,
             sq_head,
             sq_tail,
             sq_flags,
         }
     }
 
+    /// The raw file descriptor of the io_uring instance.
+    #[inline]
+    pub(crate) fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
     #[inline]
     fn sq_len(&self) -> usize {
         unsafe {
@@ -110,6 +190,11 @@ impl<'a> Submitter<'a> {
     /// You will probably want to use a more high-level API such as
     /// [`submit`](Self::submit) or [`submit_and_wait`](Self::submit_and_wait).
     ///
+    /// If the ring file descriptor has been registered with
+    /// [`register_ring_fd`](Self::register_ring_fd) by the calling thread, the registered index
+    /// is used instead of the file descriptor and [`EnterFlags::REGISTERED_RING`] is added to
+    /// `flag`.
+    ///
     /// # Safety
     ///
     /// This provides a raw interface so developer must ensure that parameters are correct.
@@ -123,16 +208,23 @@ impl<'a> Submitter<'a> {
         let arg = arg
             .map(|arg| cast_ptr(arg).cast())
             .unwrap_or_else(ptr::null);
-        let size = mem::size_of::<T>();
-        sys::io_uring_enter(
-            self.fd.as_raw_fd(),
-            to_submit,
-            min_complete,
-            flag,
-            arg,
-            size,
-        )
-        .map(|res| res as _)
+        self.enter_raw(to_submit, min_complete, flag, arg, mem::size_of::<T>())
+    }
+
+    #[inline]
+    unsafe fn enter_raw(
+        &self,
+        to_submit: u32,
+        min_complete: u32,
+        flag: u32,
+        arg: *const libc::c_void,
+        size: usize,
+    ) -> io::Result<usize> {
+        let (fd, flag) = match self.registered_ring_fd() {
+            Some(index) => (index as RawFd, flag | sys::IORING_ENTER_REGISTERED_RING),
+            None => (self.fd.as_raw_fd(), flag),
+        };
+        sys::io_uring_enter(fd, to_submit, min_complete, flag, arg, size).map(|res| res as _)
     }
 
     /// Submit all queued submission queue events to the kernel.
@@ -198,9 +290,43 @@ impl<'a> Submitter<'a> {
         want: usize,
         args: &types::SubmitArgs<'_, '_>,
     ) -> io::Result<usize> {
-        let len = self.sq_len();
         let mut flags = EnterFlags::EXT_ARG;
 
+        if args.absolute {
+            flags.insert(EnterFlags::ABS_TIMER);
+        }
+
+        let arg = cast_ptr(&args.args).cast();
+        let size = mem::size_of::<sys::io_uring_getevents_arg>();
+        unsafe { self.submit_with_ext_arg(want, flags, arg, size) }
+    }
+
+    /// Submit all queued submission queue events to the kernel and wait for at least `want`
+    /// completion events to complete, with the arguments stored in the slot `index` of the
+    /// region registered with [`register_wait_region`](Self::register_wait_region).
+    ///
+    /// Unlike [`submit_with_args`](Self::submit_with_args), the arguments are not copied from
+    /// userspace on every call. Returns `EFAULT` if `index` is out of the bounds of the region.
+    ///
+    /// Available since 6.13.
+    pub fn submit_with_registered_wait(&self, want: usize, index: usize) -> io::Result<usize> {
+        let flags = EnterFlags::EXT_ARG | EnterFlags::EXT_ARG_REG;
+
+        // The kernel expects the offset of the slot in the region in place of a pointer.
+        let offset = index.saturating_mul(mem::size_of::<types::RegWait>());
+        let size = mem::size_of::<types::RegWait>();
+        unsafe { self.submit_with_ext_arg(want, flags, offset as *const libc::c_void, size) }
+    }
+
+    unsafe fn submit_with_ext_arg(
+        &self,
+        want: usize,
+        mut flags: EnterFlags,
+        arg: *const libc::c_void,
+        size: usize,
+    ) -> io::Result<usize> {
+        let len = self.sq_len();
+
         let sq_cq_overflow = self.sq_cq_overflow();
         let need_syscall = sq_cq_overflow & self.params.is_feature_nodrop();
 
@@ -220,7 +346,7 @@ impl<'a> Submitter<'a> {
             }
         }
 
-        unsafe { self.enter(len as _, want as _, flags.bits(), Some(args)) }
+        self.enter_raw(len as _, want as _, flags.bits(), arg, size)
     }
 
     /// Wait for the submission queue to have free entries.
@@ -411,6 +537,28 @@ impl<'a> Submitter<'a> {
         Ok(ret as _)
     }
 
+    /// Restrict the kernel's allocation of direct descriptors to `len` slots starting at `offset`.
+    /// Requests using [`DestinationSlot::auto_target`](crate::types::DestinationSlot::auto_target)
+    /// will only be given slots within this range, leaving the rest of the table to be managed
+    /// by the application. Available since 6.0.
+    ///
+    /// The range must lie within the registered file table.
+    /// See also [`FixedFileTable`](crate::types::FixedFileTable).
+    pub fn register_file_alloc_range(&self, offset: u32, len: u32) -> io::Result<()> {
+        let range = sys::io_uring_file_index_range {
+            off: offset,
+            len,
+            resv: 0,
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_FILE_ALLOC_RANGE,
+            cast_ptr::<sys::io_uring_file_index_range>(&range).cast(),
+            0,
+        )
+        .map(drop)
+    }
+
     /// Register an eventfd created by [`eventfd`](libc::eventfd) with the io_uring instance.
     pub fn register_eventfd(&self, eventfd: RawFd) -> io::Result<()> {
         execute(
@@ -499,6 +647,63 @@ impl<'a> Submitter<'a> {
         .map(drop)
     }
 
+    /// Clone the fixed buffers registered with the `src` ring into this ring, so that both rings
+    /// share a single registration of the same memory. The buffers of `src` in `range` are
+    /// installed starting at index `dst_offset` of this ring, and `..` clones all of them.
+    ///
+    /// Unless `replace` is set, this ring must not have any buffers registered, otherwise `EBUSY`
+    /// is returned. With `replace`, the buffers already registered in the destination range are
+    /// replaced. An empty range, or one without an end that does not start at zero, fails with
+    /// `InvalidInput`.
+    ///
+    /// Available since 6.12, cloning a range or replacing buffers since 6.13.
+    pub fn clone_buffers_from(
+        &self,
+        src: &Submitter<'_>,
+        range: impl RangeBounds<u32>,
+        dst_offset: u32,
+        replace: bool,
+    ) -> io::Result<()> {
+        let invalid = || io::Error::from(io::ErrorKind::InvalidInput);
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.checked_add(1).ok_or_else(invalid)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => Some(end.checked_add(1).ok_or_else(invalid)?),
+            Bound::Excluded(&end) => Some(end),
+            Bound::Unbounded => None,
+        };
+
+        // The kernel clones all the buffers of `src` when `nr` is zero.
+        let nr = match end {
+            Some(end) if end > start => end - start,
+            None if start == 0 => 0,
+            _ => return Err(invalid()),
+        };
+
+        let arg = sys::io_uring_clone_buffers {
+            src_fd: src.fd.as_raw_fd() as _,
+            flags: if replace {
+                sys::IORING_REGISTER_DST_REPLACE
+            } else {
+                0
+            },
+            src_off: start,
+            dst_off: dst_offset,
+            nr,
+            ..Default::default()
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_CLONE_BUFFERS,
+            cast_ptr(&arg).cast(),
+            1,
+        )
+        .map(drop)
+    }
+
     /// Unregister all previously registered files.
     ///
     /// You do not need to explicitly call this before dropping the [`IoUring`](crate::IoUring), as
@@ -667,6 +872,39 @@ impl<'a> Submitter<'a> {
         .map(drop)
     }
 
+    /// Register a buffer ring for provided buffers, letting the kernel allocate the ring memory
+    /// with the `IOU_PBUF_RING_MMAP` flag, and map it into memory.
+    ///
+    /// The ring is zero-filled, and has `ring_entries` entries which must be a power of two.
+    /// `flags` is combined with `IOU_PBUF_RING_MMAP`.
+    ///
+    /// Available since 6.4.
+    pub fn register_buf_ring_mmap(
+        &self,
+        ring_entries: u16,
+        bgid: u16,
+        flags: u16,
+    ) -> io::Result<types::BufRingMmap> {
+        let arg = sys::io_uring_buf_reg {
+            ring_addr: 0,
+            ring_entries: ring_entries as _,
+            bgid,
+            flags: flags | sys::IOU_PBUF_RING_MMAP as u16,
+            ..Default::default()
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_PBUF_RING,
+            cast_ptr::<sys::io_uring_buf_reg>(&arg).cast(),
+            1,
+        )?;
+
+        types::BufRingMmap::new(self.fd, bgid, ring_entries).map_err(|err| {
+            let _ = self.unregister_buf_ring(bgid);
+            err
+        })
+    }
+
     /// Unregister a previously registered buffer ring.
     ///
     /// Available since 5.19.
@@ -686,6 +924,27 @@ impl<'a> Submitter<'a> {
         .map(drop)
     }
 
+    /// Get the head of a registered buffer ring, i.e. the index of the next buffer the kernel
+    /// will consume from the ring identified by `bgid`.
+    ///
+    /// Comparing it with the tail published by the application tells how many buffers remain,
+    /// so the ring can be refilled before requests start failing with `ENOBUFS`.
+    ///
+    /// Available since 6.8.
+    pub fn buf_ring_head(&self, bgid: u16) -> io::Result<u16> {
+        let mut arg = sys::io_uring_buf_status {
+            buf_group: bgid as _,
+            ..Default::default()
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_PBUF_STATUS,
+            (&mut arg as *mut sys::io_uring_buf_status).cast(),
+            1,
+        )?;
+        Ok(arg.head as _)
+    }
+
     /// Performs a synchronous cancellation request, similar to [AsyncCancel](crate::opcode::AsyncCancel),
     /// except that it completes synchronously.
     ///
@@ -741,6 +1000,198 @@ impl<'a> Submitter<'a> {
         .map(drop)
     }
 
+    /// Register the file descriptor of this io_uring instance with the kernel, so that subsequent
+    /// calls to `io_uring_enter` can use the returned index instead of the file descriptor. This
+    /// avoids looking up the ring file on every call to [`submit`](Self::submit),
+    /// [`submit_and_wait`](Self::submit_and_wait) and [`submit_with_args`](Self::submit_with_args),
+    /// all of which switch to the registered index automatically once this returns.
+    ///
+    /// The index is only valid for the calling thread, as each thread has its own table of
+    /// registered ring fds. Other threads keep entering the ring with its file descriptor. The
+    /// registration is removed by [`unregister_ring_fd`](Self::unregister_ring_fd), or when the
+    /// [`IoUring`](crate::IoUring) is dropped by the registering thread. Otherwise it holds a
+    /// reference to the ring until the registering thread exits.
+    ///
+    /// Returns `EEXIST` if the ring fd is already registered, by any thread.
+    ///
+    /// Available since 5.18.
+    pub fn register_ring_fd(&self) -> io::Result<u32> {
+        if !self.ring_index.claim() {
+            return Err(io::Error::from_raw_os_error(libc::EEXIST));
+        }
+
+        let mut rr = sys::io_uring_rsrc_update {
+            offset: u32::MAX,
+            data: self.fd.as_raw_fd() as _,
+            ..Default::default()
+        };
+        if let Err(err) = execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_RING_FDS,
+            (&mut rr as *mut sys::io_uring_rsrc_update).cast(),
+            1,
+        ) {
+            self.ring_index.clear();
+            return Err(err);
+        }
+        self.ring_index.set(rr.offset);
+        Ok(rr.offset)
+    }
+
+    /// Unregister the ring file descriptor previously registered with
+    /// [`register_ring_fd`](Self::register_ring_fd). Subsequent calls to `io_uring_enter` use the
+    /// file descriptor again.
+    ///
+    /// Returns `EINVAL` if the ring fd is not registered by the calling thread.
+    ///
+    /// Available since 5.18.
+    pub fn unregister_ring_fd(&self) -> io::Result<()> {
+        let offset = self
+            .registered_ring_fd()
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))?;
+
+        let rr = sys::io_uring_rsrc_update {
+            offset,
+            ..Default::default()
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_UNREGISTER_RING_FDS,
+            cast_ptr::<sys::io_uring_rsrc_update>(&rr).cast(),
+            1,
+        )?;
+        self.ring_index.clear();
+        Ok(())
+    }
+
+    /// The index of the ring file descriptor, if it has been registered with
+    /// [`register_ring_fd`](Self::register_ring_fd) by the calling thread.
+    #[inline]
+    pub fn registered_ring_fd(&self) -> Option<u32> {
+        self.ring_index.get()
+    }
+
+    /// Allocate a region of at least `nr` [`RegWait`](types::RegWait) slots, and register it for
+    /// use with [`submit_with_registered_wait`](Self::submit_with_registered_wait).
+    ///
+    /// The ring must have been set up with
+    /// [`setup_r_disabled`](crate::Builder::setup_r_disabled) and not be enabled yet, otherwise
+    /// `EINVAL` is returned. Only one region can be registered, further calls fail with `EBUSY`.
+    /// The kernel keeps the memory pinned until the ring is destroyed, so dropping the region
+    /// only prevents further updates of its slots.
+    ///
+    /// Available since 6.13.
+    pub fn register_wait_region(&self, nr: usize) -> io::Result<types::WaitRegion> {
+        let region = types::WaitRegion::new(nr)?;
+        let mut desc = sys::io_uring_region_desc {
+            user_addr: region.as_mut_ptr() as _,
+            size: region.size() as _,
+            flags: sys::IORING_MEM_REGION_TYPE_USER,
+            ..Default::default()
+        };
+        let reg = sys::io_uring_mem_region_reg {
+            region_uptr: (&mut desc as *mut sys::io_uring_region_desc) as _,
+            flags: sys::IORING_MEM_REGION_REG_WAIT_ARG as _,
+            ..Default::default()
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_MEM_REGION,
+            cast_ptr(&reg).cast(),
+            1,
+        )?;
+        Ok(region)
+    }
+
+    /// Select the clock used for the timeouts of [`submit_with_args`](Self::submit_with_args),
+    /// which is `CLOCK_MONOTONIC` by default. Timeouts of requests such as
+    /// [`Timeout`](crate::opcode::Timeout) are not affected.
+    ///
+    /// Available since 6.12.
+    pub fn register_clock(&self, clock: types::ClockId) -> io::Result<()> {
+        let clockid = match clock {
+            types::ClockId::Monotonic => libc::CLOCK_MONOTONIC,
+            types::ClockId::Boottime => libc::CLOCK_BOOTTIME,
+        };
+        let arg = sys::io_uring_clock_register {
+            clockid: clockid as _,
+            ..Default::default()
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_CLOCK,
+            cast_ptr(&arg).cast(),
+            0,
+        )
+        .map(drop)
+    }
+
+    /// Enable NAPI busy polling with the given settings, replacing the current ones, which are
+    /// returned.
+    ///
+    /// Returns `EINVAL` if the ring was set up with [`setup_iopoll`](crate::Builder::setup_iopoll).
+    ///
+    /// Available since 6.9.
+    pub fn register_napi(&self, napi: Napi) -> io::Result<Napi> {
+        let mut arg = napi.into_sys();
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_NAPI,
+            (&mut arg as *mut sys::io_uring_napi).cast(),
+            1,
+        )?;
+        Ok(Napi::from_sys(arg))
+    }
+
+    /// Disable NAPI busy polling, and return the current settings. The kernel does not report the
+    /// tracking mode here, so it always reads as
+    /// [`NapiTracking::Dynamic`](crate::register::NapiTracking::Dynamic).
+    ///
+    /// Available since 6.9.
+    pub fn unregister_napi(&self) -> io::Result<Napi> {
+        let mut arg = sys::io_uring_napi::default();
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_UNREGISTER_NAPI,
+            (&mut arg as *mut sys::io_uring_napi).cast(),
+            1,
+        )?;
+        Ok(Napi::from_sys(arg))
+    }
+
+    /// Add the NAPI context `napi_id` to the contexts busy polled by this ring, as reported by the
+    /// `SO_INCOMING_NAPI_ID` socket option.
+    ///
+    /// Returns `EINVAL` unless NAPI busy polling was registered with
+    /// [`NapiTracking::Static`](crate::register::NapiTracking::Static).
+    ///
+    /// Available since 6.12.
+    pub fn register_napi_id(&self, napi_id: u32) -> io::Result<()> {
+        self.napi_static_op(sys::IO_URING_NAPI_STATIC_ADD_ID, napi_id)
+    }
+
+    /// Remove the NAPI context `napi_id` added with [`register_napi_id`](Self::register_napi_id).
+    ///
+    /// Available since 6.12.
+    pub fn unregister_napi_id(&self, napi_id: u32) -> io::Result<()> {
+        self.napi_static_op(sys::IO_URING_NAPI_STATIC_DEL_ID, napi_id)
+    }
+
+    fn napi_static_op(&self, opcode: u32, napi_id: u32) -> io::Result<()> {
+        let mut arg = sys::io_uring_napi {
+            opcode: opcode as _,
+            op_param: napi_id,
+            ..Default::default()
+        };
+        execute(
+            self.fd.as_raw_fd(),
+            sys::IORING_REGISTER_NAPI,
+            (&mut arg as *mut sys::io_uring_napi).cast(),
+            1,
+        )
+        .map(drop)
+    }
+
     /// Register a netdev hw rx queue for zerocopy.
     ///
     /// Available since 6.15.
@@ -4,7 +4,7 @@ pub(crate) mod sealed {
     use super::{Fd, Fixed};
     use std::os::unix::io::RawFd;
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy)]
     pub enum Target {
         Fd(RawFd),
         Fixed(u32),
@@ -38,15 +38,26 @@ pub(crate) mod sealed {
             Target::Fixed(self.0)
         }
     }
+
+    impl UseFixed for Target {
+        #[inline]
+        fn into(self) -> Target {
+            self
+        }
+    }
 }
 
 use crate::sys;
-use crate::util::{cast_ptr, unwrap_nonzero, unwrap_u32};
+use crate::util::{cast_ptr, page_size, unwrap_nonzero, unwrap_u32, Mmap, OwnedFd};
 use bitflags::bitflags;
 use std::convert::TryFrom;
+use std::io;
 use std::marker::PhantomData;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::num::NonZeroU32;
+use std::ops::Range;
 use std::os::unix::io::RawFd;
+use std::time::Duration;
 
 #[deprecated]
 pub type RwFlags = u32;
@@ -123,6 +134,24 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Options for [`MsgRingData`](crate::opcode::MsgRingData) and
+    /// [`MsgRingSendFd`](crate::opcode::MsgRingSendFd), set with their `opcode_flags`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub struct MsgRingFlags: u32 {
+        /// Don't post a completion to the target ring.
+        ///
+        /// Only valid for [`MsgRingSendFd`](crate::opcode::MsgRingSendFd), available since 6.0.
+        const CQE_SKIP = sys::IORING_MSG_RING_CQE_SKIP;
+
+        /// Pass the `user_flags` as the flags of the completion on the target ring, which is
+        /// set by [`MsgRingData`](crate::opcode::MsgRingData) when `user_flags` is given.
+        ///
+        /// Available since 6.3.
+        const FLAGS_PASS = sys::IORING_MSG_RING_FLAGS_PASS;
+    }
+}
+
 bitflags! {
     /// Options for [`AsyncCancel`](super::AsyncCancel) and
     /// [`Submitter::register_sync_cancel`](super::Submitter::register_sync_cancel).
@@ -239,10 +268,10 @@ impl From<std::time::Duration> for Timespec {
 ///
 /// drop(args);
 /// ```
-#[repr(transparent)]
 #[derive(Default, Debug, Clone, Copy)]
 pub struct SubmitArgs<'prev: 'now, 'now> {
     pub(crate) args: sys::io_uring_getevents_arg,
+    pub(crate) absolute: bool,
     prev: PhantomData<&'prev ()>,
     now: PhantomData<&'now ()>,
 }
@@ -259,6 +288,7 @@ impl<'prev, 'now> SubmitArgs<'prev, 'now> {
 
         SubmitArgs {
             args,
+            absolute: false,
             prev: PhantomData,
             now: PhantomData,
         }
@@ -274,6 +304,7 @@ impl<'prev, 'now> SubmitArgs<'prev, 'now> {
 
         SubmitArgs {
             args: self.args,
+            absolute: self.absolute,
             prev: self.now,
             now: PhantomData,
         }
@@ -302,10 +333,143 @@ impl<'prev, 'now> SubmitArgs<'prev, 'now> {
 
         SubmitArgs {
             args: self.args,
+            absolute: self.absolute,
             prev: self.now,
             now: PhantomData,
         }
     }
+
+    /// Interpret the [`timespec`](Self::timespec) timeout as an absolute time of the clock
+    /// registered with [`Submitter::register_clock`](crate::Submitter::register_clock), which is
+    /// `CLOCK_MONOTONIC` by default, instead of as a time relative to the call.
+    ///
+    /// Available since 6.12.
+    #[inline]
+    pub fn absolute(mut self) -> Self {
+        self.absolute = true;
+        self
+    }
+}
+
+/// The `user_data` of the [`Timeout`](crate::opcode::Timeout) request which
+/// [`IoUring::wait`](crate::IoUring::wait) uses on kernels without
+/// [`is_feature_ext_arg`](crate::Parameters::is_feature_ext_arg). It must not be used by other
+/// requests, as `wait` takes the completions with this `user_data` out of the completion queue.
+pub const WAIT_TIMEOUT_USER_DATA: u64 = u64::MAX;
+
+/// The outcome of [`IoUring::wait`](crate::IoUring::wait).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    /// The requested number of completions are available.
+    Ready,
+    /// The timeout expired first. Fewer completions than requested may be available.
+    TimedOut,
+    /// The wait was interrupted by a signal.
+    Interrupted,
+}
+
+/// The arguments of a wait for completions made with
+/// [`Submitter::submit_with_registered_wait`](crate::Submitter::submit_with_registered_wait),
+/// stored in a slot of a [`WaitRegion`]. The kernel reads the slot on every wait, so it can be
+/// updated in place between waits.
+#[repr(transparent)]
+pub struct RegWait(sys::io_uring_reg_wait);
+
+impl RegWait {
+    /// Set the timeout of the wait, or wait without a timeout if `None`.
+    pub fn set_timeout(&mut self, timeout: Option<Timespec>) {
+        match timeout {
+            Some(timespec) => {
+                self.0.ts = timespec.0;
+                self.0.flags |= sys::IORING_REG_WAIT_TS;
+            }
+            None => self.0.flags &= !sys::IORING_REG_WAIT_TS,
+        }
+    }
+
+    /// Set a timeout in microseconds after which the wait returns as soon as a single completion
+    /// has been received, see [`SubmitArgs::min_wait_usec`].
+    pub fn set_min_wait_usec(&mut self, min_wait_usec: u32) {
+        self.0.min_wait_usec = min_wait_usec;
+    }
+
+    /// Set the signals to mask during the wait, or leave the signal mask untouched if `None`.
+    ///
+    /// Only the address of `sigmask` is stored, the kernel reads the mask on every wait.
+    ///
+    /// # Safety
+    ///
+    /// `sigmask` must stay valid, and must not be moved, for as long as the slot is used by a
+    /// wait, or until the signal mask of the slot is set again.
+    pub unsafe fn set_sigmask(&mut self, sigmask: Option<&libc::sigset_t>) {
+        match sigmask {
+            Some(sigmask) => {
+                self.0.sigmask = cast_ptr(sigmask) as _;
+                self.0.sigmask_sz = std::mem::size_of::<libc::sigset_t>() as _;
+            }
+            None => {
+                self.0.sigmask = 0;
+                self.0.sigmask_sz = 0;
+            }
+        }
+    }
+}
+
+/// Memory holding the [`RegWait`] slots of a ring, allocated by
+/// [`Submitter::register_wait_region`](crate::Submitter::register_wait_region).
+pub struct WaitRegion {
+    mmap: Mmap,
+    len: usize,
+}
+
+unsafe impl Send for WaitRegion {}
+unsafe impl Sync for WaitRegion {}
+
+impl WaitRegion {
+    /// Allocate zeroed memory for `len` slots, rounded up to the page size as the kernel requires.
+    pub(crate) fn new(len: usize) -> io::Result<WaitRegion> {
+        let page_size = page_size();
+        let size = len
+            .checked_mul(std::mem::size_of::<RegWait>())
+            .filter(|&size| size > 0)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let size = (size + page_size - 1) & !(page_size - 1);
+
+        Ok(WaitRegion {
+            mmap: Mmap::new_anon(size)?,
+            len: size / std::mem::size_of::<RegWait>(),
+        })
+    }
+
+    #[inline]
+    pub(crate) fn as_mut_ptr(&self) -> *mut libc::c_void {
+        self.mmap.as_mut_ptr()
+    }
+
+    #[inline]
+    pub(crate) fn size(&self) -> usize {
+        self.len * std::mem::size_of::<RegWait>()
+    }
+
+    /// The slots of the region. There may be more than requested, to fill the last page.
+    pub fn slots(&self) -> &[RegWait] {
+        unsafe { std::slice::from_raw_parts(self.mmap.as_mut_ptr().cast(), self.len) }
+    }
+
+    /// The slots of the region, for updating the arguments of future waits.
+    pub fn slots_mut(&mut self) -> &mut [RegWait] {
+        unsafe { std::slice::from_raw_parts_mut(self.mmap.as_mut_ptr().cast(), self.len) }
+    }
+}
+
+/// The clock used for the timeouts of [`SubmitArgs`], see
+/// [`Submitter::register_clock`](crate::Submitter::register_clock).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockId {
+    /// `CLOCK_MONOTONIC`, the default.
+    Monotonic,
+    /// `CLOCK_BOOTTIME`, which also advances while the system is suspended.
+    Boottime,
 }
 
 #[repr(transparent)]
@@ -361,6 +525,43 @@ impl BufRingEntry {
     }
 }
 
+/// A buf_ring allocated by the kernel and mapped into memory, returned by
+/// [`Submitter::register_buf_ring_mmap`](crate::Submitter::register_buf_ring_mmap).
+///
+/// Dropping the mapping does not unregister the ring, which must be done with
+/// [`Submitter::unregister_buf_ring`](crate::Submitter::unregister_buf_ring).
+pub struct BufRingMmap {
+    pub(crate) mmap: Mmap,
+    entries: u16,
+}
+
+unsafe impl Send for BufRingMmap {}
+unsafe impl Sync for BufRingMmap {}
+
+impl BufRingMmap {
+    /// Map the ring the kernel allocated for `bgid`.
+    pub(crate) fn new(fd: &OwnedFd, bgid: u16, entries: u16) -> io::Result<BufRingMmap> {
+        let offset =
+            sys::IORING_OFF_PBUF_RING as u64 | (u64::from(bgid) << sys::IORING_OFF_PBUF_SHIFT);
+        let len = usize::from(entries) * std::mem::size_of::<BufRingEntry>();
+
+        Ok(BufRingMmap {
+            mmap: Mmap::new(fd, offset as libc::off_t, len)?,
+            entries,
+        })
+    }
+
+    /// The address of the first entry of the ring, to be used with [`BufRingEntry::tail`].
+    pub fn as_mut_ptr(&self) -> *mut BufRingEntry {
+        self.mmap.as_mut_ptr().cast()
+    }
+
+    /// The number of entries in the ring.
+    pub fn entries(&self) -> u16 {
+        self.entries
+    }
+}
+
 /// A destination slot for sending fixed resources
 /// (e.g. [`opcode::MsgRingSendFd`](crate::opcode::MsgRingSendFd)).
 #[derive(Debug, Clone, Copy)]
@@ -405,6 +606,481 @@ impl DestinationSlot {
     }
 }
 
+/// Userspace bookkeeping for a registered file table.
+///
+/// The table is split into slots handed out by [`alloc`](Self::alloc) and a range left to the
+/// kernel allocator, which should be registered with
+/// [`Submitter::register_file_alloc_range`](crate::Submitter::register_file_alloc_range).
+/// Slots the kernel picks for [`DestinationSlot::auto_target`] requests are recorded with
+/// [`insert_allocated`](Self::insert_allocated).
+///
+/// Direct descriptors closed with the entry built by [`close`](Self::close) have their slot
+/// recycled by [`complete_close`](Self::complete_close) once the close succeeded. Slots closed
+/// any other way must be returned with [`release`](Self::release).
+#[derive(Debug, Clone)]
+pub struct FixedFileTable {
+    used: Vec<bool>,
+    free: Vec<u32>,
+    alloc_range: Range<u32>,
+    // The `user_data` and slot of the pending closes.
+    closing: Vec<(u64, u32)>,
+}
+
+impl FixedFileTable {
+    /// Create a table of `nr` slots, reserving `alloc_range` for the kernel allocator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alloc_range` does not lie within the table.
+    pub fn new(nr: u32, alloc_range: Range<u32>) -> Self {
+        assert!(
+            alloc_range.start <= alloc_range.end && alloc_range.end <= nr,
+            "allocation range out of bounds"
+        );
+
+        // lowest slots are handed out first
+        let free = (0..alloc_range.start)
+            .chain(alloc_range.end..nr)
+            .rev()
+            .collect();
+
+        FixedFileTable {
+            used: vec![false; nr as usize],
+            free,
+            alloc_range,
+            closing: Vec::new(),
+        }
+    }
+
+    /// The number of slots in the table.
+    pub fn capacity(&self) -> u32 {
+        self.used.len() as u32
+    }
+
+    /// The range of slots reserved for the kernel allocator.
+    pub fn alloc_range(&self) -> Range<u32> {
+        self.alloc_range.clone()
+    }
+
+    /// Take a free slot outside the kernel allocation range.
+    pub fn alloc(&mut self) -> Option<Fixed> {
+        let slot = self.free.pop()?;
+        self.used[slot as usize] = true;
+        Some(Fixed(slot))
+    }
+
+    /// Record a slot allocated by the kernel, as returned in the result of a request using
+    /// [`DestinationSlot::auto_target`].
+    ///
+    /// Returns `None` if the slot is outside the kernel allocation range or already in use.
+    pub fn insert_allocated(&mut self, slot: u32) -> Option<Fixed> {
+        if !self.alloc_range.contains(&slot) || self.used[slot as usize] {
+            return None;
+        }
+        self.used[slot as usize] = true;
+        Some(Fixed(slot))
+    }
+
+    /// Build the [`Close`](crate::opcode::Close) of the direct descriptor in a slot, whose
+    /// completion is recognized by `user_data` and passed to
+    /// [`complete_close`](Self::complete_close).
+    ///
+    /// Returns `None` if the slot is not in use or is already being closed.
+    pub fn close(&mut self, fixed: Fixed, user_data: u64) -> Option<crate::squeue::Entry> {
+        if !self.is_used(fixed) || self.closing.iter().any(|&(_, slot)| slot == fixed.0) {
+            return None;
+        }
+        self.closing.push((user_data, fixed.0));
+        Some(
+            crate::opcode::Close::new(fixed)
+                .build()
+                .user_data(user_data),
+        )
+    }
+
+    /// Handle the completion of a close built by [`close`](Self::close), returning the slot to
+    /// the table if the close succeeded.
+    ///
+    /// Returns the released slot, or `None` if the completion is not one of a pending close or
+    /// the close failed, in which case the slot stays in use.
+    pub fn complete_close<E: crate::cqueue::EntryMarker>(&mut self, cqe: &E) -> Option<Fixed> {
+        let cqe: crate::cqueue::Entry = cqe.clone().into();
+        let pos = self
+            .closing
+            .iter()
+            .position(|&(user_data, _)| user_data == cqe.user_data())?;
+        let (_, slot) = self.closing.swap_remove(pos);
+        if cqe.result() < 0 {
+            return None;
+        }
+        self.release(Fixed(slot));
+        Some(Fixed(slot))
+    }
+
+    /// Return a slot to the table after the file in it has been closed.
+    ///
+    /// Returns `false` if the slot was not in use.
+    pub fn release(&mut self, fixed: Fixed) -> bool {
+        match self.used.get_mut(fixed.0 as usize) {
+            Some(used) if *used => *used = false,
+            _ => return false,
+        }
+        if !self.alloc_range.contains(&fixed.0) {
+            self.free.push(fixed.0);
+        }
+        true
+    }
+
+    /// Whether the slot is currently in use.
+    pub fn is_used(&self, fixed: Fixed) -> bool {
+        self.used.get(fixed.0 as usize).copied().unwrap_or(false)
+    }
+}
+
+/// An owned `msghdr` for [`SendMsg`](crate::opcode::SendMsg),
+/// [`SendMsgZc`](crate::opcode::SendMsgZc), [`RecvMsg`](crate::opcode::RecvMsg) and
+/// [`RecvMsgMulti`](crate::opcode::RecvMsgMulti), with its iovecs, address and control messages.
+///
+/// The header is kept at a stable address, so that [`as_ptr`](Self::as_ptr) stays valid when the
+/// `MsgHdr` is moved. It must not be dropped or modified before the operation completes.
+///
+/// A message to send passes exactly the control messages added to it, while a message to
+/// receive passes the room reserved with [`control_capacity`](Self::control_capacity). Only
+/// buffers added with [`buf_mut`](Self::buf_mut) may be received into.
+///
+/// ```
+/// # use ntex_io_uring::{opcode, types};
+/// let payload = [0u8; 3000];
+/// let msg = types::MsgHdr::new()
+///     .buf(&payload)
+///     .addr("127.0.0.1:4242".parse().unwrap())
+///     .udp_segment(1000);
+///
+/// let send_e = opcode::SendMsg::new(types::Fd(3), msg.as_ptr());
+/// ```
+pub struct MsgHdr<'a> {
+    inner: Box<MsgHdrInner>,
+    iovecs: Vec<libc::iovec>,
+    // u64 words keep the control messages aligned.
+    control: Vec<u64>,
+    // The room taken by the control messages to send.
+    control_len: usize,
+    // The room reserved for the control messages to receive.
+    capacity: usize,
+    // Whether a buffer was added with `buf`, which must not be written to.
+    read_only: bool,
+    bufs: PhantomData<&'a mut [u8]>,
+}
+
+struct MsgHdrInner {
+    hdr: libc::msghdr,
+    addr: libc::sockaddr_storage,
+}
+
+// From `linux/udp.h`, which are the same on every architecture but missing from older libc.
+const UDP_SEGMENT: i32 = 103;
+const UDP_GRO: i32 = 104;
+// From `asm/socket.h`, which libc does not provide.
+#[cfg(not(any(target_arch = "sparc", target_arch = "sparc64")))]
+const SO_TIMESTAMPING_NEW: i32 = 65;
+#[cfg(any(target_arch = "sparc", target_arch = "sparc64"))]
+const SO_TIMESTAMPING_NEW: i32 = 0x43;
+
+impl<'a> MsgHdr<'a> {
+    /// Create an empty `msghdr`.
+    pub fn new() -> Self {
+        MsgHdr {
+            inner: Box::new(MsgHdrInner {
+                hdr: unsafe { std::mem::zeroed() },
+                addr: unsafe { std::mem::zeroed() },
+            }),
+            iovecs: Vec::new(),
+            control: Vec::new(),
+            control_len: 0,
+            capacity: 0,
+            read_only: false,
+            bufs: PhantomData,
+        }
+    }
+
+    /// Add a buffer to send from.
+    ///
+    /// The message can then no longer be used to receive, [`as_mut_ptr`](Self::as_mut_ptr)
+    /// returns `None`.
+    pub fn buf(mut self, buf: &'a [u8]) -> Self {
+        self.read_only = true;
+        self.iovec(buf.as_ptr() as *mut u8, buf.len())
+    }
+
+    /// Add a buffer to receive into.
+    pub fn buf_mut(self, buf: &'a mut [u8]) -> Self {
+        self.iovec(buf.as_mut_ptr(), buf.len())
+    }
+
+    fn iovec(mut self, base: *mut u8, len: usize) -> Self {
+        self.iovecs.push(libc::iovec {
+            iov_base: base.cast(),
+            iov_len: len,
+        });
+        self.update();
+        self
+    }
+
+    /// Set the destination address of a sent message.
+    pub fn addr(mut self, addr: SocketAddr) -> Self {
+        let len = match addr {
+            SocketAddr::V4(addr) => {
+                let sin = libc::sockaddr_in {
+                    sin_family: libc::AF_INET as _,
+                    sin_port: addr.port().to_be(),
+                    sin_addr: libc::in_addr {
+                        s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                    },
+                    sin_zero: [0; 8],
+                };
+                unsafe {
+                    std::ptr::write(
+                        (&mut self.inner.addr as *mut libc::sockaddr_storage).cast(),
+                        sin,
+                    )
+                };
+                std::mem::size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(addr) => {
+                let mut sin6: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+                sin6.sin6_family = libc::AF_INET6 as _;
+                sin6.sin6_port = addr.port().to_be();
+                sin6.sin6_flowinfo = addr.flowinfo().to_be();
+                sin6.sin6_addr.s6_addr = addr.ip().octets();
+                sin6.sin6_scope_id = addr.scope_id();
+                unsafe {
+                    std::ptr::write(
+                        (&mut self.inner.addr as *mut libc::sockaddr_storage).cast(),
+                        sin6,
+                    )
+                };
+                std::mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+        self.inner.hdr.msg_namelen = len as _;
+        self.update();
+        self
+    }
+
+    /// Reserve room for the source address of a received message, see
+    /// [`source_addr`](Self::source_addr).
+    pub fn recv_addr(mut self) -> Self {
+        self.inner.hdr.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as _;
+        self.update();
+        self
+    }
+
+    /// Reserve `len` bytes for the control messages of a received message.
+    ///
+    /// Use [`cmsg_space`] to compute the room needed by a control message. This has no effect
+    /// on a message to send, whose control messages are added with [`cmsg`](Self::cmsg).
+    pub fn control_capacity(mut self, len: usize) -> Self {
+        if len > self.control.len() * 8 {
+            self.control.resize((len + 7) / 8, 0);
+        }
+        self.capacity = std::cmp::max(self.capacity, len);
+        self.update();
+        self
+    }
+
+    /// Pass file descriptors to the peer of a unix socket, with `SCM_RIGHTS`.
+    pub fn rights(self, fds: &[RawFd]) -> Self {
+        let data: Vec<u8> = fds.iter().flat_map(|fd| fd.to_ne_bytes()).collect();
+        self.cmsg(libc::SOL_SOCKET, libc::SCM_RIGHTS, &data)
+    }
+
+    /// Split the payload into UDP datagrams of `size` bytes, with `UDP_SEGMENT`.
+    pub fn udp_segment(self, size: u16) -> Self {
+        self.cmsg(libc::SOL_UDP, UDP_SEGMENT, &size.to_ne_bytes())
+    }
+
+    /// Send from the source address `src` on the interface `ifindex` (or any if 0), with
+    /// `IP_PKTINFO`.
+    pub fn pktinfo(self, ifindex: u32, src: Ipv4Addr) -> Self {
+        // struct in_pktinfo { int ipi_ifindex; struct in_addr ipi_spec_dst, ipi_addr; }
+        let mut data = [0u8; 12];
+        data[..4].copy_from_slice(&ifindex.to_ne_bytes());
+        data[4..8].copy_from_slice(&src.octets());
+        self.cmsg(libc::IPPROTO_IP, libc::IP_PKTINFO, &data)
+    }
+
+    /// Send from the source address `src` on the interface `ifindex` (or any if 0), with
+    /// `IPV6_PKTINFO`.
+    pub fn pktinfo_v6(self, ifindex: u32, src: Ipv6Addr) -> Self {
+        // struct in6_pktinfo { struct in6_addr ipi6_addr; int ipi6_ifindex; }
+        let mut data = [0u8; 20];
+        data[..16].copy_from_slice(&src.octets());
+        data[16..].copy_from_slice(&ifindex.to_ne_bytes());
+        self.cmsg(libc::IPPROTO_IPV6, libc::IPV6_PKTINFO, &data)
+    }
+
+    /// Request timestamps of the message, with `SO_TIMESTAMPING` and the `SOF_TIMESTAMPING_*`
+    /// `flags`.
+    pub fn timestamping(self, flags: u32) -> Self {
+        self.cmsg(
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPING,
+            &flags.to_ne_bytes(),
+        )
+    }
+
+    /// Add a control message of `level` and `ty` carrying `data`.
+    pub fn cmsg(mut self, level: i32, ty: i32, data: &[u8]) -> Self {
+        let offset = self.control_len;
+        let space = cmsg_space(data.len());
+        if offset + space > self.control.len() * 8 {
+            self.control.resize((offset + space + 7) / 8, 0);
+        }
+
+        unsafe {
+            let buf = self.control.as_mut_ptr().cast::<u8>().add(offset);
+            let header = libc::cmsghdr {
+                cmsg_len: libc::CMSG_LEN(data.len() as _) as _,
+                cmsg_level: level,
+                cmsg_type: ty,
+            };
+            std::ptr::write_unaligned(buf.cast(), header);
+            let data_offset = libc::CMSG_LEN(0) as usize;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), buf.add(data_offset), data.len());
+        }
+        self.control_len += space;
+        self.update();
+        self
+    }
+
+    /// A pointer to the `msghdr`, for the opcodes sending a message, or for the template of
+    /// [`RecvMsgMulti`](crate::opcode::RecvMsgMulti).
+    pub fn as_ptr(&self) -> *const libc::msghdr {
+        &self.inner.hdr
+    }
+
+    /// A pointer to the `msghdr`, for the opcodes receiving a message.
+    ///
+    /// This resets the control length to the reserved room, so the message can be received
+    /// into again. Returns `None` if a buffer was added with [`buf`](Self::buf), since the
+    /// kernel would write to it.
+    pub fn as_mut_ptr(&mut self) -> Option<*mut libc::msghdr> {
+        if self.read_only {
+            return None;
+        }
+        let control_len = std::cmp::max(self.capacity, self.control_len);
+        self.set_control(control_len);
+        Some(&mut self.inner.hdr)
+    }
+
+    /// The `msghdr`, for [`RecvMsgOut::parse`].
+    pub fn as_msghdr(&self) -> &libc::msghdr {
+        &self.inner.hdr
+    }
+
+    /// The source address of a message received with [`recv_addr`](Self::recv_addr).
+    pub fn source_addr(&self) -> Option<SocketAddr> {
+        let addr = &self.inner.addr;
+        let len = self.inner.hdr.msg_namelen as usize;
+        match addr.ss_family as i32 {
+            libc::AF_INET if len >= std::mem::size_of::<libc::sockaddr_in>() => {
+                let sin = unsafe {
+                    &*(addr as *const libc::sockaddr_storage).cast::<libc::sockaddr_in>()
+                };
+                let ip = Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes());
+                Some(SocketAddr::new(ip.into(), u16::from_be(sin.sin_port)))
+            }
+            libc::AF_INET6 if len >= std::mem::size_of::<libc::sockaddr_in6>() => {
+                let sin6 = unsafe {
+                    &*(addr as *const libc::sockaddr_storage).cast::<libc::sockaddr_in6>()
+                };
+                Some(SocketAddr::V6(std::net::SocketAddrV6::new(
+                    Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                    u16::from_be(sin6.sin6_port),
+                    u32::from_be(sin6.sin6_flowinfo),
+                    sin6.sin6_scope_id,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    /// The control messages, as updated by the kernel for a received message.
+    pub fn control(&self) -> &[u8] {
+        // `msg_controllen` is not a `usize` on every libc.
+        let controllen: usize = self.inner.hdr.msg_controllen as _;
+        let len = std::cmp::min(controllen, self.control.len() * 8);
+        unsafe { std::slice::from_raw_parts(self.control.as_ptr().cast(), len) }
+    }
+
+    /// Iterate over the control messages of a received message.
+    pub fn control_messages(&self) -> ControlMessages<'_> {
+        ControlMessages::new(self.control())
+    }
+
+    /// The flags of a received message, such as `MSG_TRUNC` or `MSG_CTRUNC`.
+    pub fn flags(&self) -> i32 {
+        self.inner.hdr.msg_flags
+    }
+
+    // Point the header at the buffers, which move when they grow.
+    fn update(&mut self) {
+        let hdr = &mut self.inner.hdr;
+        hdr.msg_iov = self.iovecs.as_mut_ptr();
+        hdr.msg_iovlen = self.iovecs.len() as _;
+        hdr.msg_name = if hdr.msg_namelen > 0 {
+            (&mut self.inner.addr as *mut libc::sockaddr_storage).cast()
+        } else {
+            std::ptr::null_mut()
+        };
+        // Trailing room after the control messages to send would fail `CMSG_OK`.
+        let control_len = if self.control_len > 0 {
+            self.control_len
+        } else {
+            self.capacity
+        };
+        self.set_control(control_len);
+    }
+
+    fn set_control(&mut self, len: usize) {
+        let hdr = &mut self.inner.hdr;
+        hdr.msg_control = if len > 0 {
+            self.control.as_mut_ptr().cast()
+        } else {
+            std::ptr::null_mut()
+        };
+        hdr.msg_controllen = len as _;
+    }
+}
+
+impl Default for MsgHdr<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for MsgHdr<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MsgHdr")
+            .field("iovecs", &self.iovecs.len())
+            .field("namelen", &self.inner.hdr.msg_namelen)
+            .field("controllen", &self.inner.hdr.msg_controllen)
+            .field("flags", &self.inner.hdr.msg_flags)
+            .finish()
+    }
+}
+
+// SAFETY: the buffers are borrowed for `'a` as `&[u8]` or `&mut [u8]`, which are both `Send`, and
+// the header, address and control messages it points to are owned by the `MsgHdr`. It is not
+// `Sync`, as the kernel may write through the pointers handed out while it is shared.
+unsafe impl Send for MsgHdr<'_> {}
+
+/// The room a control message with `len` bytes of data takes in the control buffer, as
+/// `CMSG_SPACE`.
+pub fn cmsg_space(len: usize) -> usize {
+    unsafe { libc::CMSG_SPACE(len as _) as usize }
+}
+
 /// Helper structure for parsing the result of a multishot [`opcode::RecvMsg`](crate::opcode::RecvMsg).
 #[derive(Debug)]
 pub struct RecvMsgOut<'buf> {
@@ -560,6 +1236,222 @@ impl<'buf> RecvMsgOut<'buf> {
     pub fn flags(&self) -> u32 {
         self.header.flags
     }
+
+    /// Iterate over the control messages of `control_data()`.
+    ///
+    /// When [`is_control_data_truncated`](Self::is_control_data_truncated) is `true`, the
+    /// trailing control messages which did not fit are missing.
+    pub fn control_messages(&self) -> ControlMessages<'buf> {
+        ControlMessages::new(self.control_data)
+    }
+}
+
+/// An iterator over the control messages of a received message, see
+/// [`RecvMsgOut::control_messages`].
+///
+/// A control message which was cut by the end of the buffer is skipped, along with the ones
+/// following it.
+#[derive(Debug, Clone)]
+pub struct ControlMessages<'a> {
+    buf: &'a [u8],
+}
+
+/// A control message of a received message, see [`ControlMessages`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ControlMessage<'a> {
+    /// File descriptors passed with `SCM_RIGHTS`, which are now owned by the receiver.
+    Rights(Vec<RawFd>),
+    /// The size of the segments of a coalesced UDP datagram, with `UDP_GRO`.
+    UdpGroSegment(u16),
+    /// The interface and addresses an IPv4 packet was received on, with `IP_PKTINFO`.
+    PktInfo {
+        ifindex: u32,
+        /// The local address of the interface.
+        local: Ipv4Addr,
+        /// The destination address of the packet.
+        dst: Ipv4Addr,
+    },
+    /// The interface and destination address an IPv6 packet was received on, with
+    /// `IPV6_PKTINFO`.
+    PktInfoV6 { ifindex: u32, dst: Ipv6Addr },
+    /// The software, legacy and raw hardware timestamps of the message, with
+    /// `SO_TIMESTAMPING`. Timestamps which were not generated are zero.
+    Timestamping([Duration; 3]),
+    /// An error from the error queue, with `IP_RECVERR` or `IPV6_RECVERR`.
+    RecvErr(ExtendedError),
+    /// Any other control message.
+    Raw { level: i32, ty: i32, data: &'a [u8] },
+}
+
+/// An extended socket error, the `sock_extended_err` of a [`ControlMessage::RecvErr`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedError {
+    errno: u32,
+    origin: u8,
+    kind: u8,
+    code: u8,
+    info: u32,
+    data: u32,
+}
+
+impl<'a> ControlMessages<'a> {
+    /// Iterate over the control messages of `buf`, laid out as `msghdr.msg_control`.
+    pub fn new(buf: &'a [u8]) -> Self {
+        ControlMessages { buf }
+    }
+}
+
+impl<'a> Iterator for ControlMessages<'a> {
+    type Item = ControlMessage<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header_len = unsafe { libc::CMSG_LEN(0) } as usize;
+        if self.buf.len() < header_len {
+            return None;
+        }
+
+        // The buffer may not be aligned, e.g. in a provided buffer.
+        let header: libc::cmsghdr = unsafe { std::ptr::read_unaligned(self.buf.as_ptr().cast()) };
+        let len: usize = header.cmsg_len as _;
+        if len < header_len || len > self.buf.len() {
+            self.buf = &[];
+            return None;
+        }
+        let data = &self.buf[header_len..len];
+        let space = cmsg_space(len - header_len);
+        self.buf = self.buf.get(space..).unwrap_or(&[]);
+
+        Some(ControlMessage::parse(
+            header.cmsg_level,
+            header.cmsg_type,
+            data,
+        ))
+    }
+}
+
+impl<'a> ControlMessage<'a> {
+    fn parse(level: i32, ty: i32, data: &'a [u8]) -> Self {
+        fn u32_at(data: &[u8], offset: usize) -> u32 {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&data[offset..offset + 4]);
+            u32::from_ne_bytes(bytes)
+        }
+        fn i64_at(data: &[u8], offset: usize) -> i64 {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&data[offset..offset + 8]);
+            i64::from_ne_bytes(bytes)
+        }
+        fn ipv4_at(data: &[u8], offset: usize) -> Ipv4Addr {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&data[offset..offset + 4]);
+            Ipv4Addr::from(bytes)
+        }
+
+        match (level, ty) {
+            (libc::SOL_SOCKET, libc::SCM_RIGHTS) => ControlMessage::Rights(
+                data.chunks_exact(4)
+                    .map(|fd| u32_at(fd, 0) as RawFd)
+                    .collect(),
+            ),
+            (libc::SOL_UDP, UDP_GRO) if data.len() >= 4 => {
+                ControlMessage::UdpGroSegment(u32_at(data, 0) as u16)
+            }
+            // struct in_pktinfo { int ipi_ifindex; struct in_addr ipi_spec_dst, ipi_addr; }
+            (libc::IPPROTO_IP, libc::IP_PKTINFO) if data.len() >= 12 => ControlMessage::PktInfo {
+                ifindex: u32_at(data, 0),
+                local: ipv4_at(data, 4),
+                dst: ipv4_at(data, 8),
+            },
+            // struct in6_pktinfo { struct in6_addr ipi6_addr; int ipi6_ifindex; }
+            (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) if data.len() >= 20 => {
+                let mut dst = [0; 16];
+                dst.copy_from_slice(&data[..16]);
+                ControlMessage::PktInfoV6 {
+                    ifindex: u32_at(data, 16),
+                    dst: Ipv6Addr::from(dst),
+                }
+            }
+            // struct scm_timestamping { struct timespec ts[3]; }, with a 64-bit time in the
+            // `_NEW` variant.
+            (libc::SOL_SOCKET, libc::SO_TIMESTAMPING | SO_TIMESTAMPING_NEW) => {
+                let (time_len, field_len) = if ty == SO_TIMESTAMPING_NEW {
+                    (8, 8)
+                } else {
+                    let time_len = std::mem::size_of::<libc::time_t>();
+                    (time_len, std::mem::size_of::<libc::timespec>() - time_len)
+                };
+                let ts_len = time_len + field_len;
+                if data.len() < 3 * ts_len {
+                    return ControlMessage::Raw { level, ty, data };
+                }
+
+                let field = |offset: usize, len: usize| match len {
+                    8 => i64_at(data, offset),
+                    _ => i64::from(u32_at(data, offset) as i32),
+                };
+                let mut ts = [Duration::ZERO; 3];
+                for (i, ts) in ts.iter_mut().enumerate() {
+                    let sec = field(i * ts_len, time_len);
+                    let nsec = field(i * ts_len + time_len, field_len);
+                    *ts = Duration::new(sec as u64, nsec as u32);
+                }
+                ControlMessage::Timestamping(ts)
+            }
+            // struct sock_extended_err { __u32 ee_errno; __u8 ee_origin, ee_type, ee_code,
+            // ee_pad; __u32 ee_info, ee_data; }
+            (libc::IPPROTO_IP, libc::IP_RECVERR) | (libc::IPPROTO_IPV6, libc::IPV6_RECVERR)
+                if data.len() >= 16 =>
+            {
+                ControlMessage::RecvErr(ExtendedError {
+                    errno: u32_at(data, 0),
+                    origin: data[4],
+                    kind: data[5],
+                    code: data[6],
+                    info: u32_at(data, 8),
+                    data: u32_at(data, 12),
+                })
+            }
+            _ => ControlMessage::Raw { level, ty, data },
+        }
+    }
+}
+
+impl ExtendedError {
+    /// The error, `ee_errno`.
+    pub fn error(&self) -> io::Error {
+        io::Error::from_raw_os_error(self.errno as i32)
+    }
+
+    /// The raw error number, `ee_errno`.
+    pub fn errno(&self) -> u32 {
+        self.errno
+    }
+
+    /// Where the error comes from, one of the `SO_EE_ORIGIN_*` constants.
+    pub fn origin(&self) -> u8 {
+        self.origin
+    }
+
+    /// The type of the error, such as the ICMP type for `SO_EE_ORIGIN_ICMP`.
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// The code of the error, such as the ICMP code for `SO_EE_ORIGIN_ICMP`.
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+
+    /// Additional information, such as the MTU for `EMSGSIZE`.
+    pub fn info(&self) -> u32 {
+        self.info
+    }
+
+    /// Additional data, such as the range of notifications for `SO_EE_ORIGIN_ZEROCOPY`.
+    pub fn data(&self) -> u32 {
+        self.data
+    }
 }
 
 /// [CancelBuilder] constructs match criteria for request cancellation.
@@ -704,6 +1596,31 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn fixed_file_table_splits_slots() {
+        let mut table = FixedFileTable::new(6, 2..4);
+
+        assert_eq!(table.alloc().map(|f| f.0), Some(0));
+        assert_eq!(table.alloc().map(|f| f.0), Some(1));
+        assert_eq!(table.alloc().map(|f| f.0), Some(4));
+        assert_eq!(table.alloc().map(|f| f.0), Some(5));
+        assert!(table.alloc().is_none());
+
+        assert_eq!(table.insert_allocated(3).map(|f| f.0), Some(3));
+        assert!(table.insert_allocated(3).is_none());
+        assert!(table.insert_allocated(4).is_none());
+
+        assert!(table.release(Fixed(1)));
+        assert!(!table.release(Fixed(1)));
+        assert!(table.release(Fixed(3)));
+        assert!(!table.is_used(Fixed(3)));
+        assert!(!table.release(Fixed(6)));
+
+        assert_eq!(table.alloc().map(|f| f.0), Some(1));
+        assert!(table.alloc().is_none());
+        assert_eq!(table.insert_allocated(3).map(|f| f.0), Some(3));
+    }
+
     #[test]
     fn timespec_from_duration_converts_correctly() {
         let duration = Duration::new(2, 500);
@@ -1,5 +1,5 @@
 use std::num::NonZeroU32;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic;
 use std::{io, ptr};
 
@@ -20,13 +20,22 @@ pub(crate) struct Mmap {
 impl Mmap {
     /// Map `len` bytes starting from the offset `offset` in the file descriptor `fd` into memory.
     pub fn new(fd: &OwnedFd, offset: libc::off_t, len: usize) -> io::Result<Mmap> {
+        Mmap::map(fd.as_raw_fd(), offset, len, 0)
+    }
+
+    /// Allocate `len` bytes of anonymous memory, which is page aligned and zero-filled.
+    pub fn new_anon(len: usize) -> io::Result<Mmap> {
+        Mmap::map(-1, 0, len, libc::MAP_ANONYMOUS)
+    }
+
+    fn map(fd: RawFd, offset: libc::off_t, len: usize, flags: i32) -> io::Result<Mmap> {
         unsafe {
             match libc::mmap(
                 ptr::null_mut(),
                 len,
                 libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_SHARED | libc::MAP_POPULATE,
-                fd.as_raw_fd(),
+                libc::MAP_SHARED | libc::MAP_POPULATE | flags,
+                fd,
                 offset,
             ) {
                 libc::MAP_FAILED => Err(io::Error::last_os_error()),
@@ -52,12 +61,6 @@ impl Mmap {
     pub fn as_mut_ptr(&self) -> *mut libc::c_void {
         self.addr.as_ptr()
     }
-
-    /// Get a pointer to the data at the given offset.
-    #[inline]
-    pub unsafe fn offset(&self, offset: u32) -> *mut libc::c_void {
-        self.as_mut_ptr().add(offset as usize)
-    }
 }
 
 impl Drop for Mmap {
@@ -115,6 +118,11 @@ mod fd {
     }
 }
 
+/// The size of a memory page, as reported by `sysconf(_SC_PAGESIZE)`.
+pub(crate) fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
 #[inline(always)]
 pub(crate) unsafe fn unsync_load(u: *const atomic::AtomicU32) -> u32 {
     *u.cast::<u32>()